@@ -1,8 +1,8 @@
-use std::array::IntoIter;
+use std::{array::IntoIter, path::Path};
 
 use harmony_rust_sdk::{
     api::{
-        auth::{auth_step::Step, next_step_request::form_fields::Field},
+        auth::{auth_step::Step, next_step_request::form_fields::Field, CheckLoggedInRequest},
         chat::{
             EventSource, GetGuildChannelsRequest, GetGuildListRequest, InviteId, JoinGuildRequest,
         },
@@ -25,6 +25,8 @@ use tokio::{
 
 const SERVER_ADDR: &str = "https://localhost:2289";
 const PASSWORD: &str = "123456789Ab";
+/// Sessions of the bench accounts by email, in the format of the tests' cache.
+const SESSION_CACHE: &str = "bench_sessions.json";
 
 #[derive(Copy, Clone, Default)]
 struct BenchData {
@@ -276,11 +278,34 @@ async fn register(client: &Client, email: &str) -> ClientResult<()> {
     Ok(())
 }
 
-async fn new_test_client(email: &str) -> ClientResult<(Client, BenchData)> {
+/// Connects with the cached session of `email` if the server still accepts
+/// it, and logs in or registers otherwise.
+async fn authenticated_client(email: &str) -> ClientResult<Client> {
+    let cached = session_cache::load(Path::new(SESSION_CACHE), email)
+        .map_err(|err| tracing::warn!("{}", err))
+        .ok()
+        .flatten();
+    if let Some(session) = cached {
+        let client = Client::new(SERVER_ADDR.parse().unwrap(), Some(session)).await?;
+        if client.call(CheckLoggedInRequest::new()).await.is_ok() {
+            return Ok(client);
+        }
+    }
+
     let client = Client::new(SERVER_ADDR.parse().unwrap(), None).await?;
     if login(&client, email).await.is_err() {
         register(&client, email).await?;
     }
+    if let Some(session) = client.auth_status().session() {
+        if let Err(err) = session_cache::save(Path::new(SESSION_CACHE), email, session) {
+            tracing::warn!("{}", err);
+        }
+    }
+    Ok(client)
+}
+
+async fn new_test_client(email: &str) -> ClientResult<(Client, BenchData)> {
+    let client = authenticated_client(email).await?;
 
     let guild_id = if let Some(entry) = client.call(GetGuildListRequest {}).await?.guilds.pop() {
         entry.guild_id
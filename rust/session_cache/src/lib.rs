@@ -0,0 +1,86 @@
+//! The file sessions are cached in between runs, shared by the tests and the
+//! benches so both read and write one format.
+
+use std::{collections::BTreeMap, fs::OpenOptions, io::Write, path::Path, sync::Mutex};
+#[cfg(unix)]
+use std::{
+    fs::Permissions,
+    os::unix::fs::{OpenOptionsExt, PermissionsExt},
+};
+
+use harmony_rust_sdk::api::auth::Session;
+use serde::{Deserialize, Serialize};
+
+/// Held while reading or writing a cache file, since clients log in
+/// concurrently.
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// Content of a cache file.
+#[derive(Default, Serialize, Deserialize)]
+struct Cache {
+    /// Sessions by whatever key the caller identifies accounts with.
+    sessions: BTreeMap<String, Entry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    user_id: u64,
+    session_token: String,
+}
+
+impl Cache {
+    /// Loads the cache at `path`, empty if there is no such file.
+    fn load(path: &Path) -> Result<Self, String> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| format!("failed to read sessions {}: {}", path.display(), err))?;
+        serde_json::from_str(&content)
+            .map_err(|err| format!("invalid sessions {}: {}", path.display(), err))
+    }
+
+    /// Writes the cache to `path`, only readable by its owner since it holds
+    /// session tokens.
+    fn save(&self, path: &Path) -> Result<(), String> {
+        let content = serde_json::to_string_pretty(self).expect("sessions are always valid json");
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        options
+            .open(path)
+            .and_then(|mut file| {
+                // files created before the mode was set keep their permissions otherwise
+                #[cfg(unix)]
+                file.set_permissions(Permissions::from_mode(0o600))?;
+                file.write_all(content.as_bytes())
+            })
+            .map_err(|err| format!("failed to write sessions {}: {}", path.display(), err))
+    }
+}
+
+/// The session saved under `key` in the cache at `path`.
+pub fn load(path: &Path, key: &str) -> Result<Option<Session>, String> {
+    let _lock = LOCK.lock().unwrap();
+    let mut cache = Cache::load(path)?;
+    Ok(cache.sessions.remove(key).map(|entry| Session {
+        user_id: entry.user_id,
+        session_token: entry.session_token,
+        ..Default::default()
+    }))
+}
+
+/// Saves `session` under `key` in the cache at `path`, keeping the others.
+pub fn save(path: &Path, key: &str, session: &Session) -> Result<(), String> {
+    let _lock = LOCK.lock().unwrap();
+    let mut cache = Cache::load(path).unwrap_or_default();
+    cache.sessions.insert(
+        key.to_string(),
+        Entry {
+            user_id: session.user_id,
+            session_token: session.session_token.clone(),
+        },
+    );
+    cache.save(path)
+}
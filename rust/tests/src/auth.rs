@@ -0,0 +1,185 @@
+//! Walks whatever auth flow the target has, instead of assuming its steps.
+//!
+//! Choices are picked and forms filled from a [`Profile`]. When the target
+//! refuses an answer, the driver goes back if it can and tries the next
+//! choice, so logging in falls back to registering.
+
+use std::{collections::HashMap, fmt};
+
+use harmony_rust_sdk::{
+    api::auth::{
+        auth_step::{form::FormField, Step},
+        next_step_request::form_fields::Field,
+        AuthStep, BeginAuthRequest, NextStepRequest, StepBackRequest, StreamStepsRequest,
+    },
+    client::{api::auth::AuthStepResponse, Client},
+};
+
+use crate::{called, config::Config, expect::Expect, suite::TestResult};
+
+/// Steps after which the driver gives up, in case the target loops.
+const MAX_STEPS: usize = 32;
+
+/// What to answer auth steps with.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    /// Options to pick in choice steps, in order of preference.
+    pub choices: Vec<String>,
+    /// Values of form fields by name.
+    pub fields: HashMap<String, String>,
+}
+
+impl Profile {
+    /// The profile of the account `email`, using the tester's password and
+    /// the auth settings of `config`.
+    pub fn account(config: &Config, email: &str, username: &str) -> Self {
+        let mut fields = config
+            .auth_fields
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect::<HashMap<_, _>>();
+        fields.insert("email".to_string(), email.to_string());
+        fields.insert("username".to_string(), username.to_string());
+        fields.insert(
+            "password".to_string(),
+            config.password.clone().expect("no tester password?"),
+        );
+        Self {
+            choices: config.auth_choices.clone(),
+            fields,
+        }
+    }
+
+    /// The value to fill `field` with, found by name then by type.
+    fn fill(&self, field: &FormField) -> Option<Field> {
+        let value = self.fields.get(&field.name).or_else(|| {
+            let by_type = match field.r#type.as_str() {
+                "email" => "email",
+                "password" | "new-password" => "password",
+                _ => return None,
+            };
+            self.fields.get(by_type)
+        })?;
+        Some(match field.r#type.as_str() {
+            "password" | "new-password" => Field::Bytes(value.as_bytes().to_vec()),
+            "number" => Field::Number(value.parse().ok()?),
+            _ => Field::String(value.clone()),
+        })
+    }
+}
+
+/// Everything the driver received and answered during an auth flow.
+///
+/// Form values are left out, since they include the password.
+#[derive(Default)]
+pub struct Transcript {
+    pub lines: Vec<String>,
+}
+
+impl Transcript {
+    pub fn push(&mut self, line: String) {
+        tracing::info!("auth: {}", line);
+        self.lines.push(line);
+    }
+}
+
+impl fmt::Debug for Transcript {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(&self.lines).finish()
+    }
+}
+
+/// Authenticates `client` by answering every step of the target's auth flow
+/// from `profile`.
+pub async fn authenticate(client: &Client, profile: &Profile) -> TestResult<Transcript> {
+    let mut transcript = Transcript::default();
+    called!(BeginAuthRequest, client.begin_auth()).await?;
+    let mut steps = Expect::new(called!(StreamStepsRequest, client.auth_stream()).await?);
+    transcript.push("sent initial".to_string());
+    called!(
+        NextStepRequest,
+        client.next_auth_step(AuthStepResponse::Initial)
+    )
+    .await?;
+
+    // options already picked, by choice title
+    let mut picked = HashMap::<String, Vec<String>>::new();
+    for _ in 0..MAX_STEPS {
+        let step = steps.next().await?;
+        transcript.push(format!("received {}", describe(&step)));
+
+        let answer = match &step.step {
+            Some(Step::Session(_)) => return Ok(transcript),
+            // the target moves on by itself
+            Some(Step::Waiting(_)) => continue,
+            Some(Step::Choice(choice)) => {
+                let picked = picked.entry(choice.title.clone()).or_default();
+                let option = profile
+                    .choices
+                    .iter()
+                    .find(|option| choice.options.contains(option) && !picked.contains(option));
+                option.map(|option| {
+                    picked.push(option.clone());
+                    transcript.push(format!("sent choice {}", option));
+                    AuthStepResponse::Choice(option.clone())
+                })
+            }
+            Some(Step::Form(form)) => {
+                let fields = form
+                    .fields
+                    .iter()
+                    .map(|field| profile.fill(field))
+                    .collect::<Option<Vec<_>>>();
+                fields.map(|fields| {
+                    transcript.push(format!("sent form {}", form.title));
+                    AuthStepResponse::form(fields)
+                })
+            }
+            None => None,
+        };
+
+        let refusal = match answer {
+            Some(answer) => match called!(NextStepRequest, client.next_auth_step(answer)).await {
+                Ok(_) => continue,
+                Err(err) => err.to_string(),
+            },
+            None => "nothing in the profile answers it".to_string(),
+        };
+        transcript.push(format!("refused: {}", refusal));
+        if !step.can_go_back {
+            return Err(format!(
+                "stuck at {}: {}, transcript: {:?}",
+                describe(&step),
+                refusal,
+                transcript
+            )
+            .into());
+        }
+        transcript.push("sent back".to_string());
+        called!(StepBackRequest, client.prev_auth_step()).await?;
+    }
+    Err(format!(
+        "no session after {} steps, transcript: {:?}",
+        MAX_STEPS, transcript
+    )
+    .into())
+}
+
+fn describe(step: &AuthStep) -> String {
+    match &step.step {
+        Some(Step::Choice(choice)) => {
+            format!("choice {} of {}", choice.title, choice.options.join(", "))
+        }
+        Some(Step::Form(form)) => {
+            let fields = form
+                .fields
+                .iter()
+                .map(|field| format!("{}: {}", field.name, field.r#type))
+                .collect::<Vec<_>>();
+            format!("form {} with {}", form.title, fields.join(", "))
+        }
+        Some(Step::Waiting(waiting)) => format!("waiting {:?}", waiting),
+        Some(Step::Session(_)) => "session".to_string(),
+        None => "empty step".to_string(),
+    }
+}
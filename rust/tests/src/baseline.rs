@@ -0,0 +1,161 @@
+use std::{path::Path, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+use crate::runner::{Outcome, RecordKind, TestRecord};
+
+/// Outcome and latency of every test of a run, to compare later runs with.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Baseline {
+    tests: Vec<Entry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    target: String,
+    name: String,
+    /// Whether the test passed on every run.
+    passed: bool,
+    /// Median duration of the runs where the test passed.
+    duration_ns: Option<u64>,
+}
+
+/// Differences between a baseline and a later run.
+#[derive(Debug, Default)]
+pub struct Comparison {
+    pub newly_failing: Vec<String>,
+    pub newly_passing: Vec<String>,
+    pub regressions: Vec<Regression>,
+}
+
+/// A test that got slower than the baseline allows.
+#[derive(Debug)]
+pub struct Regression {
+    pub test: String,
+    pub before: Duration,
+    pub after: Duration,
+}
+
+impl Baseline {
+    /// Tests that were skipped on every run are left out.
+    pub fn from_records(records: &[TestRecord]) -> Self {
+        let mut tests: Vec<(Entry, Vec<Duration>)> = Vec::new();
+        for record in records
+            .iter()
+            .filter(|r| r.kind == RecordKind::Test && r.outcome != Outcome::Skipped)
+        {
+            let target = record.path.first().cloned().unwrap_or_default();
+            let index = match tests
+                .iter()
+                .position(|(e, _)| e.target == target && e.name == record.name)
+            {
+                Some(index) => index,
+                None => {
+                    let entry = Entry {
+                        target,
+                        name: record.name.clone(),
+                        passed: true,
+                        duration_ns: None,
+                    };
+                    tests.push((entry, Vec::new()));
+                    tests.len() - 1
+                }
+            };
+            let (entry, durations) = &mut tests[index];
+            if record.outcome == Outcome::Passed {
+                durations.push(record.duration);
+            } else {
+                entry.passed = false;
+            }
+        }
+
+        let tests = tests
+            .into_iter()
+            .map(|(mut entry, mut durations)| {
+                durations.sort_unstable();
+                entry.duration_ns = durations
+                    .get(durations.len() / 2)
+                    .map(|median| median.as_nanos() as u64);
+                entry
+            })
+            .collect();
+        Self { tests }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| format!("failed to read baseline {}: {}", path.display(), err))?;
+        serde_json::from_str(&content)
+            .map_err(|err| format!("invalid baseline {}: {}", path.display(), err))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let content = serde_json::to_string_pretty(self).expect("baseline is always valid json");
+        std::fs::write(path, content)
+            .map_err(|err| format!("failed to write baseline {}: {}", path.display(), err))
+    }
+
+    /// Compares a later run to this baseline.
+    ///
+    /// A test regressed if its median latency grew by more than `threshold`
+    /// percent. Tests missing from either run are ignored.
+    pub fn compare(&self, current: &Baseline, threshold: f64) -> Comparison {
+        let mut comparison = Comparison::default();
+        for after in &current.tests {
+            let before = match self
+                .tests
+                .iter()
+                .find(|e| e.target == after.target && e.name == after.name)
+            {
+                Some(before) => before,
+                None => continue,
+            };
+            let test = format!("{} / {}", after.target, after.name);
+            match (before.passed, after.passed) {
+                (true, false) => comparison.newly_failing.push(test.clone()),
+                (false, true) => comparison.newly_passing.push(test.clone()),
+                _ => {}
+            }
+            if let (Some(before), Some(after)) = (before.duration_ns, after.duration_ns) {
+                if after as f64 > before as f64 * (1.0 + threshold / 100.0) {
+                    comparison.regressions.push(Regression {
+                        test,
+                        before: Duration::from_nanos(before),
+                        after: Duration::from_nanos(after),
+                    });
+                }
+            }
+        }
+        comparison
+    }
+}
+
+impl Comparison {
+    pub fn has_regressions(&self) -> bool {
+        !self.newly_failing.is_empty() || !self.regressions.is_empty()
+    }
+
+    pub fn log(&self) {
+        for test in &self.newly_failing {
+            error!("newly failing: {}", test);
+        }
+        for test in &self.newly_passing {
+            info!("newly passing: {}", test);
+        }
+        for regression in &self.regressions {
+            warn!(
+                "latency regression: {}: {:.3} ms -> {:.3} ms",
+                regression.test,
+                regression.before.as_secs_f64() * 1000.0,
+                regression.after.as_secs_f64() * 1000.0
+            );
+        }
+        info!(
+            "compared to baseline: {} newly failing, {} newly passing, {} latency regressions",
+            self.newly_failing.len(),
+            self.newly_passing.len(),
+            self.regressions.len()
+        );
+    }
+}
@@ -0,0 +1,479 @@
+//! Assertions recorded as checks of the current test.
+//!
+//! Checks don't stop the test when they fail, unless made hard with
+//! [`Check::hard`], in which case the test fails and the tests depending on
+//! it are skipped:
+//!
+//! ```ignore
+//! check_len!(response.members, 1).hard()?;
+//! ```
+
+use std::{
+    fmt::{self, Debug},
+    ops::Sub,
+    time::Duration,
+};
+
+use crate::runner::{RecordKind, TestContext};
+
+/// Lines of unchanged context kept around differences.
+const DIFF_CONTEXT: usize = 3;
+
+/// Parts of the identifiers of errors servers return for their own failures.
+const INTERNAL_ERRORS: &[&str] = &["internal-error", "internal-server-error"];
+
+/// A recorded check.
+#[derive(Debug)]
+pub struct Check {
+    failure: Option<String>,
+}
+
+impl Check {
+    /// Turns a failed check into an error, to stop the test with `?`.
+    pub fn hard(self) -> Result<(), CheckFailed> {
+        match self.failure {
+            Some(failure) => Err(CheckFailed(failure)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Error of a failed hard check.
+#[derive(Debug)]
+pub struct CheckFailed(String);
+
+impl fmt::Display for CheckFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "check unsuccessful: {}", self.0)
+    }
+}
+
+impl std::error::Error for CheckFailed {}
+
+#[doc(hidden)]
+pub fn record(name: &str, result: Result<(), String>) -> Check {
+    let context = TestContext::current();
+    match result {
+        Ok(()) => {
+            context.passed(RecordKind::Check, name, Duration::ZERO, None);
+            Check { failure: None }
+        }
+        Err(err) => {
+            context.failed(RecordKind::Check, name, Duration::ZERO, err.clone());
+            Check { failure: Some(err) }
+        }
+    }
+}
+
+#[doc(hidden)]
+pub fn eq<A, B>(left: &A, right: &B) -> Result<(), String>
+where
+    A: PartialEq<B> + Debug + ?Sized,
+    B: Debug + ?Sized,
+{
+    if left == right {
+        Ok(())
+    } else {
+        Err(mismatch(left, "!=", right))
+    }
+}
+
+#[doc(hidden)]
+pub fn ne<A, B>(left: &A, right: &B) -> Result<(), String>
+where
+    A: PartialEq<B> + Debug + ?Sized,
+    B: Debug + ?Sized,
+{
+    if left != right {
+        Ok(())
+    } else {
+        Err(format!("both sides are {:?}", left))
+    }
+}
+
+#[doc(hidden)]
+pub fn within<T, D>(left: T, right: T, tolerance: D) -> Result<(), String>
+where
+    T: PartialOrd + Sub<Output = D> + Copy + Debug,
+    D: PartialOrd + Debug,
+{
+    let difference = if left > right {
+        left - right
+    } else {
+        right - left
+    };
+    if difference <= tolerance {
+        Ok(())
+    } else {
+        Err(format!(
+            "{:?} and {:?} differ by {:?}, more than {:?}",
+            left, right, difference, tolerance
+        ))
+    }
+}
+
+/// Errors are matched by the identifier field of their debug output, since
+/// the client doesn't expose the identifier of every kind of error.
+#[doc(hidden)]
+pub fn err<T: Debug, E: Debug>(result: &Result<T, E>, identifiers: &[&str]) -> Result<(), String> {
+    let err = match result {
+        Ok(value) => return Err(format!("expected an error, got {:#?}", value)),
+        Err(err) => format!("{:?}", err),
+    };
+    let identifier = identifier(&err);
+    let internal = match identifier {
+        Some(identifier) => INTERNAL_ERRORS
+            .iter()
+            .any(|internal| identifier.contains(internal)),
+        None => false,
+    };
+    if internal {
+        Err(format!("got an internal error: {}", err))
+    } else if identifiers.is_empty() || identifiers.iter().any(|id| identifier == Some(*id)) {
+        Ok(())
+    } else {
+        Err(format!(
+            "expected {}, got {}",
+            identifiers.join(" or "),
+            err
+        ))
+    }
+}
+
+/// The `identifier` field in the debug output of an error, if it has one.
+fn identifier(err: &str) -> Option<&str> {
+    const FIELD: &str = "identifier: \"";
+    let start = err.find(FIELD)? + FIELD.len();
+    let len = err[start..].find('"')?;
+    Some(&err[start..start + len])
+}
+
+/// Describes two values that should have compared the other way, as a line
+/// diff of their pretty printed forms when they don't fit on one line.
+fn mismatch<A: Debug + ?Sized, B: Debug + ?Sized>(left: &A, op: &str, right: &B) -> String {
+    let (left_pretty, right_pretty) = (format!("{:#?}", left), format!("{:#?}", right));
+    if !left_pretty.contains('\n') && !right_pretty.contains('\n') {
+        return format!("{:?} {} {:?}", left, op, right);
+    }
+    format!(
+        "left {} right\n--- left\n+++ right\n{}",
+        op,
+        diff(&left_pretty, &right_pretty)
+    )
+}
+
+/// Line diff of `left` and `right`, only keeping a few unchanged lines around
+/// each difference.
+fn diff(left: &str, right: &str) -> String {
+    let left = left.lines().collect::<Vec<_>>();
+    let right = right.lines().collect::<Vec<_>>();
+
+    // lengths of the longest common subsequences of every pair of suffixes
+    let mut lcs = vec![vec![0usize; right.len() + 1]; left.len() + 1];
+    for i in (0..left.len()).rev() {
+        for j in (0..right.len()).rev() {
+            lcs[i][j] = if left[i] == right[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < left.len() || j < right.len() {
+        if i < left.len() && j < right.len() && left[i] == right[j] {
+            lines.push((' ', left[i]));
+            i += 1;
+            j += 1;
+        } else if i < left.len() && (j == right.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(('-', left[i]));
+            i += 1;
+        } else {
+            lines.push(('+', right[j]));
+            j += 1;
+        }
+    }
+
+    let changed = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, (tag, _))| *tag != ' ')
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    let near_change = |index: usize| {
+        changed
+            .iter()
+            .any(|changed| index + DIFF_CONTEXT >= *changed && index <= changed + DIFF_CONTEXT)
+    };
+
+    let mut out = String::new();
+    let mut elided = false;
+    for (index, (tag, line)) in lines.iter().enumerate() {
+        if near_change(index) {
+            out.push(*tag);
+            out.push(' ');
+            out.push_str(line);
+            out.push('\n');
+            elided = false;
+        } else if !elided {
+            out.push_str("  ...\n");
+            elided = true;
+        }
+    }
+    out
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __record_check {
+    ($name:expr, $result:expr) => {{
+        let result: ::std::result::Result<(), ::std::string::String> = $result;
+        if let ::std::result::Result::Err(err) = &result {
+            ::tracing::error!("check unsuccessful: {}", err);
+        }
+        $crate::check::record($name, result)
+    }};
+}
+
+/// Checks that both sides are equal.
+#[macro_export]
+macro_rules! check {
+    ($left:expr, $right:expr $(,)?) => {
+        $crate::__record_check!(
+            concat!(stringify!($left), " == ", stringify!($right)),
+            $crate::check::eq(&$left, &$right)
+        )
+    };
+}
+
+/// Checks that both sides are different.
+#[macro_export]
+macro_rules! check_ne {
+    ($left:expr, $right:expr $(,)?) => {
+        $crate::__record_check!(
+            concat!(stringify!($left), " != ", stringify!($right)),
+            $crate::check::ne(&$left, &$right)
+        )
+    };
+}
+
+/// Checks that a value matches a pattern.
+#[macro_export]
+macro_rules! check_matches {
+    ($value:expr, $($pattern:pat)|+ $(if $guard:expr)? $(,)?) => {
+        $crate::__record_check!(
+            concat!(
+                stringify!($value),
+                " matches ",
+                stringify!($($pattern)|+ $(if $guard)?)
+            ),
+            match &$value {
+                $($pattern)|+ $(if $guard)? => ::std::result::Result::Ok(()),
+                value => ::std::result::Result::Err(format!(
+                    "{:#?} does not match {}",
+                    value,
+                    stringify!($($pattern)|+ $(if $guard)?)
+                )),
+            }
+        )
+    };
+}
+
+/// Checks that a string contains a pattern, or that a slice contains an item.
+#[macro_export]
+macro_rules! check_contains {
+    ($haystack:expr, $needle:expr $(,)?) => {
+        $crate::__record_check!(
+            concat!(stringify!($haystack), " contains ", stringify!($needle)),
+            {
+                let haystack = &$haystack;
+                let needle = $needle;
+                if haystack.contains(needle) {
+                    ::std::result::Result::Ok(())
+                } else {
+                    ::std::result::Result::Err(format!(
+                        "{:#?} does not contain {:?}",
+                        haystack, needle
+                    ))
+                }
+            }
+        )
+    };
+}
+
+/// Checks the number of items of a collection.
+#[macro_export]
+macro_rules! check_len {
+    ($collection:expr, $len:expr $(,)?) => {
+        $crate::__record_check!(
+            concat!(stringify!($collection), ".len() == ", stringify!($len)),
+            {
+                let collection = &$collection;
+                let len = $len;
+                if collection.len() == len {
+                    ::std::result::Result::Ok(())
+                } else {
+                    ::std::result::Result::Err(format!(
+                        "expected {} items, got {}: {:#?}",
+                        len,
+                        collection.len(),
+                        collection
+                    ))
+                }
+            }
+        )
+    };
+}
+
+/// Checks that an option has a value.
+#[macro_export]
+macro_rules! check_some {
+    ($option:expr $(,)?) => {
+        $crate::__record_check!(
+            concat!(stringify!($option), " is some"),
+            if $option.is_some() {
+                ::std::result::Result::Ok(())
+            } else {
+                ::std::result::Result::Err("got none".to_string())
+            }
+        )
+    };
+}
+
+/// Checks that two times, or any other values that can be subtracted, are
+/// at most `tolerance` apart.
+#[macro_export]
+macro_rules! check_within {
+    ($left:expr, $right:expr, $tolerance:expr $(,)?) => {
+        $crate::__record_check!(
+            concat!(
+                stringify!($left),
+                " == ",
+                stringify!($right),
+                " within ",
+                stringify!($tolerance)
+            ),
+            $crate::check::within($left, $right, $tolerance)
+        )
+    };
+}
+
+/// Checks that a result is an error with one of the given identifiers, or
+/// with any identifier if none is given. Internal errors always fail.
+///
+/// Identifiers only known at runtime are given as a slice after `in`.
+#[macro_export]
+macro_rules! check_err {
+    ($result:expr, in $identifiers:expr $(,)?) => {
+        $crate::__record_check!(
+            concat!(stringify!($result), " fails with ", stringify!($identifiers)),
+            $crate::check::err(&$result, &$identifiers)
+        )
+    };
+    ($result:expr $(, $identifier:literal)* $(,)?) => {
+        $crate::__record_check!(
+            concat!(stringify!($result), " fails" $(, " with ", $identifier)*),
+            $crate::check::err(&$result, &[$($identifier),*])
+        )
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Debugs like the errors of the client carrying an identifier.
+    #[derive(Debug)]
+    struct EndpointError {
+        identifier: &'static str,
+    }
+
+    fn refused(identifier: &'static str) -> Result<(), EndpointError> {
+        Err(EndpointError { identifier })
+    }
+
+    #[test]
+    fn diff_keeps_context_around_changes() {
+        let left = (1..=10)
+            .map(|n| n.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let right = left.replace('6', "six");
+        assert_eq!(
+            diff(&left, &right),
+            "  ...\n  3\n  4\n  5\n- 6\n+ six\n  7\n  8\n  9\n  ...\n"
+        );
+    }
+
+    #[test]
+    fn diff_elides_between_distant_changes() {
+        let left = (1..=12)
+            .map(|n| n.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let right = left.replacen('1', "one", 1).replace("12", "twelve");
+        assert_eq!(
+            diff(&left, &right),
+            "- 1\n+ one\n  2\n  3\n  4\n  ...\n  9\n  10\n  11\n- 12\n+ twelve\n"
+        );
+    }
+
+    #[test]
+    fn diff_of_added_lines() {
+        assert_eq!(diff("a\nb", "a\nb\nc"), "  a\n  b\n+ c\n");
+    }
+
+    #[test]
+    fn identifier_of_debug_output() {
+        let err = format!(
+            "{:?}",
+            EndpointError {
+                identifier: "h.bad-session"
+            }
+        );
+        assert_eq!(identifier(&err), Some("h.bad-session"));
+        assert_eq!(
+            identifier(r#"A { identifier: "h.first" } B { identifier: "h.second" }"#),
+            Some("h.first")
+        );
+        assert_eq!(identifier(r#"identifier: """#), Some(""));
+    }
+
+    #[test]
+    fn identifier_missing_or_unterminated() {
+        assert_eq!(identifier("connection refused"), None);
+        assert_eq!(identifier(r#"identifier: "h.cut"#), None);
+        assert_eq!(identifier(r#"identifier: h.unquoted"#), None);
+    }
+
+    #[test]
+    fn internal_errors_always_fail() {
+        for identifier in ["h.internal-error", "internal-server-error"] {
+            let failure = err(&refused(identifier), &[]).unwrap_err();
+            assert!(failure.starts_with("got an internal error"), "{}", failure);
+            assert!(err(&refused(identifier), &[identifier]).is_err());
+        }
+    }
+
+    #[test]
+    fn refusals_match_identifiers() {
+        assert_eq!(err(&refused("h.not-joined"), &[]), Ok(()));
+        assert_eq!(
+            err(
+                &refused("h.not-joined"),
+                &["h.bad-guild-id", "h.not-joined"]
+            ),
+            Ok(())
+        );
+        let failure = err(&refused("h.not-joined"), &["h.bad-guild-id"]).unwrap_err();
+        assert!(
+            failure.starts_with("expected h.bad-guild-id, got"),
+            "{}",
+            failure
+        );
+        let failure = err(&Ok::<_, EndpointError>(()), &[]).unwrap_err();
+        assert!(failure.starts_with("expected an error"), "{}", failure);
+    }
+}
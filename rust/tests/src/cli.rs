@@ -0,0 +1,199 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{plan::Selection, suite::Policy};
+
+const USAGE: &str = "usage: tests [--list] [--config <path>] [--fake] [--target <name>]...
+             [--filter <glob>]... [--skip <glob>]...
+             [--tag <tag>]... [--skip-tag <tag>]...
+             [--timeout <secs>] [--global-timeout <secs>] [--retries <n>]
+             [--repeat <n>] [--baseline <path>] [--save-baseline <path>]
+             [--regression-threshold <percent>]
+             [--junit <path>] [--json <path>] [--tap <path>] [--html <path>]
+             [--coverage <path>]
+             [--fuzz <n>] [--fuzz-seed <n>] [--fuzz-dir <path>] [--replay <path>]...
+
+Targets, credentials and urls are read from the config file, `tests.toml` by
+default, and can be overridden with TESTER_EMAIL, TESTER_PASSWORD,
+TESTS_EXTERNAL_URL, TESTS_INSTANT_VIEW_URL, TESTS_SESSION_CACHE and
+TESTS_<TARGET>_<FIELD>.
+Sessions are cached in `sessions.json` by default and reused while the
+target accepts them, set TESTS_SESSION_CACHE to an empty value to disable it.
+The fresh login test always goes through the auth flow, cached session or not.
+With --fake the suite runs against an in-process server instead of the
+configured targets, without needing network access or credentials.
+Tests matching a filter or tag also run the tests they depend on.
+Tests time out after 30 seconds by default, and are retried up to --retries
+times with exponential backoff when they fail with a connection error.
+A comparison table is printed to stderr when running against multiple targets.
+With --repeat the suite runs n times against every target, and latency
+statistics of every test are printed to stderr.
+With --baseline the run fails if a test passing in the baseline now fails, or
+if its median latency grew by more than the threshold, 20% by default.
+Tags are auth, chat, media, destructive and slow.
+With --fuzz the suite doesn't run, n random and mutated bodies are sent to every
+endpoint instead, and the ones the target answers with a 5xx status, never
+answers or drops the connection on are minimized and saved in --fuzz-dir,
+`fuzz` by default. --replay sends saved inputs again, from files or directories.
+Fuzzing calls every endpoint with the tester's session, including destructive
+ones, so only fuzz servers you own.
+Report paths can be `-` to write to stdout, logs then go to stderr.";
+
+#[derive(Debug, Default)]
+pub struct Options {
+    /// Print the selected tests instead of running them.
+    pub list: bool,
+    /// Config file to load instead of `tests.toml`.
+    pub config: Option<PathBuf>,
+    /// Run against an in-process fake server instead of the configured targets.
+    pub fake: bool,
+    /// Names of the targets to run against, all of them if empty.
+    pub targets: Vec<String>,
+    pub selection: Selection,
+    /// Timeout and retries of tests that don't override them.
+    pub policy: Policy,
+    /// How long the whole run may take.
+    pub global_timeout: Option<Duration>,
+    /// How many times the suite runs against every target.
+    pub repeat: u32,
+    /// Results of an earlier run to compare this one with.
+    pub baseline: Option<PathBuf>,
+    /// Where to save the results of this run as a baseline.
+    pub save_baseline: Option<PathBuf>,
+    /// How many percent slower than the baseline a test may get.
+    pub regression_threshold: f64,
+    /// Where to write a JUnit XML report of the run.
+    pub junit: Option<PathBuf>,
+    /// Where to stream newline delimited JSON events of the run.
+    pub json: Option<PathBuf>,
+    /// Where to stream TAP results of the run.
+    pub tap: Option<PathBuf>,
+    /// Where to write an HTML report of the run.
+    pub html: Option<PathBuf>,
+    /// Where to write which endpoints the run called, and whether they succeeded.
+    pub coverage: Option<PathBuf>,
+    /// How many inputs to fuzz every endpoint with, instead of running the suite.
+    pub fuzz: Option<u32>,
+    /// Seed of the fuzzed inputs, random if unset.
+    pub fuzz_seed: Option<u64>,
+    /// Where inputs the target failed on are saved.
+    pub fuzz_dir: PathBuf,
+    /// Saved inputs to send again, instead of running the suite.
+    pub replay: Vec<PathBuf>,
+}
+
+impl Options {
+    pub fn from_env() -> Result<Self, String> {
+        Self::parse(std::env::args().skip(1))
+    }
+
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options {
+            repeat: 1,
+            regression_threshold: 20.0,
+            fuzz_dir: PathBuf::from("fuzz"),
+            ..Options::default()
+        };
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--list" => options.list = true,
+                "--config" => options.config = Some(value(&arg, args.next())?.into()),
+                "--fake" => options.fake = true,
+                "--target" => options.targets.push(value(&arg, args.next())?),
+                "--filter" => options.selection.filters.push(value(&arg, args.next())?),
+                "--skip" => options.selection.skips.push(value(&arg, args.next())?),
+                "--tag" => options
+                    .selection
+                    .tags
+                    .push(value(&arg, args.next())?.parse()?),
+                "--skip-tag" => options
+                    .selection
+                    .skip_tags
+                    .push(value(&arg, args.next())?.parse()?),
+                "--timeout" => options.policy.timeout = seconds(&arg, args.next())?,
+                "--global-timeout" => options.global_timeout = Some(seconds(&arg, args.next())?),
+                "--retries" => {
+                    options.policy.retries = value(&arg, args.next())?
+                        .parse()
+                        .map_err(|err| format!("invalid value for {}: {}", arg, err))?
+                }
+                "--repeat" => {
+                    options.repeat = value(&arg, args.next())?
+                        .parse()
+                        .map_err(|err| format!("invalid value for {}: {}", arg, err))?;
+                    if options.repeat == 0 {
+                        return Err(format!("{} must be at least 1", arg));
+                    }
+                }
+                "--baseline" => options.baseline = Some(value(&arg, args.next())?.into()),
+                "--save-baseline" => options.save_baseline = Some(value(&arg, args.next())?.into()),
+                "--regression-threshold" => {
+                    options.regression_threshold = value(&arg, args.next())?
+                        .parse()
+                        .map_err(|err| format!("invalid value for {}: {}", arg, err))?;
+                    if !options.regression_threshold.is_finite()
+                        || options.regression_threshold < 0.0
+                    {
+                        return Err(format!("{} must be a positive percentage", arg));
+                    }
+                }
+                "--junit" => options.junit = Some(value(&arg, args.next())?.into()),
+                "--json" => options.json = Some(value(&arg, args.next())?.into()),
+                "--tap" => options.tap = Some(value(&arg, args.next())?.into()),
+                "--html" => options.html = Some(value(&arg, args.next())?.into()),
+                "--coverage" => options.coverage = Some(value(&arg, args.next())?.into()),
+                "--fuzz" => {
+                    let inputs = value(&arg, args.next())?
+                        .parse()
+                        .map_err(|err| format!("invalid value for {}: {}", arg, err))?;
+                    if inputs == 0 {
+                        return Err(format!("{} must be at least 1", arg));
+                    }
+                    options.fuzz = Some(inputs);
+                }
+                "--fuzz-seed" => {
+                    options.fuzz_seed = Some(
+                        value(&arg, args.next())?
+                            .parse()
+                            .map_err(|err| format!("invalid value for {}: {}", arg, err))?,
+                    )
+                }
+                "--fuzz-dir" => options.fuzz_dir = value(&arg, args.next())?.into(),
+                "--replay" => options.replay.push(value(&arg, args.next())?.into()),
+                "-h" | "--help" => return Err(USAGE.to_string()),
+                x => return Err(format!("unknown argument {}\n{}", x, USAGE)),
+            }
+        }
+        Ok(options)
+    }
+
+    /// Whether any report is written to stdout.
+    pub fn reports_to_stdout(&self) -> bool {
+        [
+            &self.junit,
+            &self.json,
+            &self.tap,
+            &self.html,
+            &self.coverage,
+        ]
+        .iter()
+        .any(|path| path.as_deref() == Some(Path::new("-")))
+    }
+}
+
+fn value(arg: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("expected a value after {}\n{}", arg, USAGE))
+}
+
+fn seconds(arg: &str, next: Option<String>) -> Result<Duration, String> {
+    let secs = value(arg, next)?
+        .parse::<f64>()
+        .map_err(|err| format!("invalid value for {}: {}", arg, err))?;
+    if secs.is_finite() && secs > 0.0 {
+        Ok(Duration::from_secs_f64(secs))
+    } else {
+        Err(format!("{} must be a positive number of seconds", arg))
+    }
+}
@@ -0,0 +1,274 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+const DEFAULT_PATH: &str = "tests.toml";
+
+/// A server the suite is ran against.
+#[derive(Debug, Clone)]
+pub struct TestData {
+    pub name: String,
+    pub server: String,
+    pub name_res: String,
+    /// Where `POST`ing a session token ends that session, on targets that
+    /// can. The protocol has no logout endpoint, so it's set per target.
+    pub revoke_url: Option<String>,
+    /// Identifiers of the errors the target refuses calls with, by the kind
+    /// of refusal the suite names them. The protocol doesn't define them, so
+    /// refusals of kinds left out only have to not be internal errors.
+    pub errors: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub email: String,
+    pub password: Option<String>,
+    pub external_url: String,
+    pub instant_view_url: String,
+    /// Options picked in auth choice steps, in order of preference.
+    pub auth_choices: Vec<String>,
+    /// Values of auth form fields other than the email, username and
+    /// password, by field name.
+    pub auth_fields: BTreeMap<String, String>,
+    /// File sessions are cached in between runs, if any.
+    pub session_cache: Option<PathBuf>,
+    pub targets: Vec<TestData>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            email: "rust_sdk_test@example.com".to_string(),
+            password: None,
+            external_url: "https://cdn.discordapp.com/attachments/855956335689728010/855957272039260210/32b13e7ff8cb6b271db2c51aa9d6bcfb94250c7a8554c3e91fc1a9b64607ee9e.png".to_string(),
+            instant_view_url: "https://duckduckgo.com/".to_string(),
+            auth_choices: vec!["login".to_string(), "register".to_string()],
+            auth_fields: BTreeMap::new(),
+            session_cache: Some(PathBuf::from("sessions.json")),
+            targets: vec![TestData {
+                name: "scherzo".to_string(),
+                server: "https://chat.harmonyapp.io:2289".to_string(),
+                name_res: "https://chat.harmonyapp.io".to_string(),
+                revoke_url: None,
+                errors: BTreeMap::new(),
+            }],
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawConfig {
+    email: Option<String>,
+    password: Option<String>,
+    external_url: Option<String>,
+    instant_view_url: Option<String>,
+    auth: Option<RawAuth>,
+    session_cache: Option<String>,
+    targets: Option<Vec<RawTarget>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawAuth {
+    choices: Option<Vec<String>>,
+    fields: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawTarget {
+    name: Option<String>,
+    server: Option<String>,
+    name_res: Option<String>,
+    revoke_url: Option<String>,
+    errors: Option<BTreeMap<String, String>>,
+}
+
+impl TestData {
+    /// The identifiers `kind` of refusal is expected with, none if any will do.
+    pub fn refusal(&self, kind: &str) -> Vec<&str> {
+        self.errors
+            .get(kind)
+            .map(String::as_str)
+            .into_iter()
+            .collect()
+    }
+}
+
+impl Config {
+    /// Email of another account of the tester, with `role` added as a
+    /// subaddress of the tester's email.
+    pub fn email_for(&self, role: &str) -> String {
+        match self.email.split_once('@') {
+            Some((local, domain)) => format!("{}+{}@{}", local, role, domain),
+            None => format!("{}+{}", self.email, role),
+        }
+    }
+
+    /// Loads the config file at `path`, or `tests.toml` if it exists, then
+    /// applies environment variable overrides.
+    ///
+    /// Anything left unset keeps its default value. Targets defined in the
+    /// file must set every field, either in the file or through
+    /// `TESTS_<TARGET>_<FIELD>` variables.
+    pub fn load(path: Option<&Path>) -> Result<Self, String> {
+        let env_path = std::env::var("TESTS_CONFIG").ok();
+        let path = path
+            .or_else(|| env_path.as_deref().map(Path::new))
+            .or_else(|| Some(Path::new(DEFAULT_PATH)).filter(|path| path.exists()));
+        let raw = match path {
+            Some(path) => {
+                let content = std::fs::read_to_string(path)
+                    .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+                toml::from_str::<RawConfig>(&content)
+                    .map_err(|err| format!("invalid config {}: {}", path.display(), err))?
+            }
+            None => RawConfig::default(),
+        };
+        Self::from_raw(raw)
+    }
+
+    fn from_raw(raw: RawConfig) -> Result<Self, String> {
+        let default = Config::default();
+        let default_targets = default.targets;
+        let targets = raw
+            .targets
+            .unwrap_or_else(|| default_targets.into_iter().map(RawTarget::from).collect())
+            .into_iter()
+            .enumerate()
+            .map(|(i, target)| target.resolve(i))
+            .collect::<Result<Vec<_>, _>>()?;
+        if targets.is_empty() {
+            return Err("config field `targets` has no entries".to_string());
+        }
+        for (i, target) in targets.iter().enumerate() {
+            if targets[..i].iter().any(|other| other.name == target.name) {
+                return Err(format!("target name `{}` is used twice", target.name));
+            }
+            for (field, url) in [("server", &target.server), ("name_res", &target.name_res)] {
+                check_url(&format!("targets[{}].{}", i, field), url)?;
+            }
+            if let Some(url) = &target.revoke_url {
+                check_url(&format!("targets[{}].revoke_url", i), url)?;
+            }
+        }
+
+        let auth = raw.auth.unwrap_or_default();
+        let config = Config {
+            email: env("TESTER_EMAIL").or(raw.email).unwrap_or(default.email),
+            password: env("TESTER_PASSWORD").or(raw.password),
+            external_url: env("TESTS_EXTERNAL_URL")
+                .or(raw.external_url)
+                .unwrap_or(default.external_url),
+            instant_view_url: env("TESTS_INSTANT_VIEW_URL")
+                .or(raw.instant_view_url)
+                .unwrap_or(default.instant_view_url),
+            auth_choices: auth.choices.unwrap_or(default.auth_choices),
+            auth_fields: auth.fields.unwrap_or(default.auth_fields),
+            // an empty path disables the cache
+            session_cache: match env("TESTS_SESSION_CACHE").or(raw.session_cache) {
+                Some(path) if path.is_empty() => None,
+                Some(path) => Some(PathBuf::from(path)),
+                None => default.session_cache,
+            },
+            targets,
+        };
+        check_url("external_url", &config.external_url)?;
+        check_url("instant_view_url", &config.instant_view_url)?;
+        if config.auth_choices.is_empty() {
+            return Err("config field `auth.choices` has no entries".to_string());
+        }
+        Ok(config)
+    }
+
+    /// Makes sure everything needed to authenticate is set.
+    pub fn check_credentials(&self) -> Result<(), String> {
+        match self.password.as_deref() {
+            Some(password) if !password.is_empty() => Ok(()),
+            _ => Err(
+                "config field `password` is missing, set it in the config file or with TESTER_PASSWORD"
+                    .to_string(),
+            ),
+        }
+    }
+}
+
+impl RawTarget {
+    fn resolve(self, index: usize) -> Result<TestData, String> {
+        let name = self
+            .name
+            .ok_or_else(|| format!("config field `targets[{}].name` is missing", index))?;
+        let field = |field: &str| format!("targets[{}].{}", index, field);
+        Ok(TestData {
+            server: required(&name, &field("server"), self.server)?,
+            name_res: required(&name, &field("name_res"), self.name_res)?,
+            revoke_url: env(&env_name(&name, &field("revoke_url"))).or(self.revoke_url),
+            name,
+            errors: self.errors.unwrap_or_default(),
+        })
+    }
+}
+
+impl From<TestData> for RawTarget {
+    fn from(data: TestData) -> Self {
+        Self {
+            name: Some(data.name),
+            server: Some(data.server),
+            name_res: Some(data.name_res),
+            revoke_url: data.revoke_url,
+            errors: Some(data.errors),
+        }
+    }
+}
+
+/// Name of the variable overriding `field` of the target called `target`.
+fn env_name(target: &str, field: &str) -> String {
+    let field = field.rsplit('.').next().unwrap_or(field);
+    format!("TESTS_{}_{}", target, field)
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn required<T: std::str::FromStr>(target: &str, field: &str, value: Option<T>) -> Result<T, String>
+where
+    T::Err: std::fmt::Display,
+{
+    let var = env_name(target, field);
+    match env(&var) {
+        Some(raw) => raw
+            .parse()
+            .map_err(|err| format!("invalid value for {}: {}", var, err)),
+        None => value.ok_or_else(|| {
+            format!(
+                "config field `{}` of target `{}` is missing, set it in the config file or with {}",
+                field, target, var
+            )
+        }),
+    }
+}
+
+fn env(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+fn check_url(field: &str, url: &str) -> Result<(), String> {
+    if url.starts_with("http://") || url.starts_with("https://") {
+        Ok(())
+    } else {
+        Err(format!(
+            "config field `{}` must be an http or https url, got `{}`",
+            field, url
+        ))
+    }
+}
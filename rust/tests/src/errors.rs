@@ -0,0 +1,268 @@
+//! Tests calling endpoints the way they shouldn't be, checking that the
+//! target refuses with the right error rather than failing internally.
+
+use harmony_rust_sdk::{
+    api::{auth::*, chat::*, profile::*},
+    client::{
+        api::{
+            auth::AuthStepResponse,
+            chat::{
+                channel::CreateChannel,
+                guild::CreateGuild,
+                message::{GetChannelMessages, SendMessage},
+                permissions::QueryHasPermission,
+            },
+        },
+        Client,
+    },
+};
+
+use crate::{
+    call, called, check_err,
+    config::{Config, TestData},
+    fixtures::{self, ChannelFixture, GuildFixture},
+    plan::Tag,
+    suite::{Suite, TestResult},
+    users,
+};
+
+/// An id no server should have given out.
+const UNKNOWN_ID: u64 = u64::MAX;
+
+/// Adds the tests of error paths.
+pub fn add(suite: &mut Suite, config: &'static Config, data: &'static TestData) {
+    suite
+        .test(
+            "unauthenticated calls",
+            &["client connection"],
+            &[Tag::Auth],
+            move |_| async move {
+                let client = Client::new(data.server.parse()?, None).await?;
+                check_err!(
+                    call!(client, CheckLoggedInRequest::new()).await,
+                    "h.blank-session",
+                    "h.bad-session"
+                );
+                check_err!(
+                    call!(client, GetGuildListRequest {}).await,
+                    "h.blank-session",
+                    "h.bad-session"
+                );
+                check_err!(
+                    call!(client, GetProfileRequest::new(UNKNOWN_ID)).await,
+                    "h.blank-session",
+                    "h.bad-session"
+                );
+                TestResult::Ok(())
+            },
+        )
+        .covers::<CheckLoggedInRequest>()
+        .covers::<GetGuildListRequest>()
+        .covers::<GetProfileRequest>();
+
+    suite
+        .test(
+            "invalid auth steps",
+            &["client auth"],
+            &[Tag::Auth],
+            move |_| async move {
+                let client = Client::new(data.server.parse()?, None).await?;
+                check_err!(
+                    call!(
+                        client,
+                        NextStepRequest {
+                            auth_id: crate::random_string(),
+                            ..Default::default()
+                        }
+                    )
+                    .await,
+                    "h.invalid-auth-id",
+                    "h.bad-auth-id"
+                );
+
+                called!(BeginAuthRequest, client.begin_auth()).await?;
+                called!(
+                    NextStepRequest,
+                    client.next_auth_step(AuthStepResponse::Initial)
+                )
+                .await?;
+                called!(
+                    NextStepRequest,
+                    client.next_auth_step(AuthStepResponse::login_choice())
+                )
+                .await?;
+                check_err!(
+                    called!(
+                        NextStepRequest,
+                        client.next_auth_step(AuthStepResponse::login_form(
+                            &config.email,
+                            &crate::random_string(),
+                        ))
+                    )
+                    .await,
+                    "h.wrong-user-or-password"
+                );
+                TestResult::Ok(())
+            },
+        )
+        .covers::<NextStepRequest>()
+        .covers::<BeginAuthRequest>();
+
+    suite
+        .test(
+            "unknown ids",
+            &["client auth", fixtures::GUILD],
+            &[Tag::Chat],
+            |deps| async move {
+                let client = deps.get::<Client>("client connection");
+                let guild = deps.get::<GuildFixture>(fixtures::GUILD);
+                check_err!(
+                    call!(client, GetGuildRequest::new(UNKNOWN_ID)).await,
+                    "h.bad-guild-id",
+                    "h.not-joined"
+                );
+                check_err!(
+                    call!(client, GetChannelMessages::new(guild.guild_id, UNKNOWN_ID)).await,
+                    "h.bad-channel-id"
+                );
+                check_err!(
+                    call!(
+                        client,
+                        GetMessageRequest {
+                            guild_id: guild.guild_id,
+                            channel_id: guild.channel_id,
+                            message_id: UNKNOWN_ID,
+                        }
+                    )
+                    .await,
+                    "h.bad-message-id"
+                );
+                check_err!(
+                    call!(client, PreviewGuildRequest::new(crate::random_string())).await,
+                    "h.bad-invite-id"
+                );
+                check_err!(
+                    call!(client, GetProfileRequest::new(UNKNOWN_ID)).await,
+                    "h.bad-user-id"
+                );
+                TestResult::Ok(())
+            },
+        )
+        .covers::<GetGuildRequest>()
+        .covers::<GetChannelMessagesRequest>()
+        .covers::<GetMessageRequest>()
+        .covers::<PreviewGuildRequest>()
+        .covers::<GetProfileRequest>();
+
+    suite
+        .test(
+            "invalid arguments",
+            &["client auth", fixtures::GUILD],
+            &[Tag::Chat],
+            move |deps| async move {
+                let client = deps.get::<Client>("client connection");
+                let guild = deps.get::<GuildFixture>(fixtures::GUILD);
+                let created = call!(client, CreateGuild::new(String::new())).await;
+                check_err!(created, in data.refusal("bad-guild-name"));
+                if let Ok(created) = created {
+                    call!(client, DeleteGuildRequest::new(created.guild_id)).await?;
+                }
+                check_err!(
+                    call!(client, CreateChannel::new(guild.guild_id, String::new())).await,
+                    in data.refusal("bad-channel-name")
+                );
+                check_err!(
+                    call!(
+                        client,
+                        SendMessage::new(guild.guild_id, guild.channel_id).text("")
+                    )
+                    .await,
+                    in data.refusal("bad-message-content")
+                );
+                check_err!(
+                    call!(
+                        client,
+                        QueryHasPermission::new(guild.guild_id, "not a permission".to_string())
+                    )
+                    .await,
+                    in data.refusal("bad-permission")
+                );
+                TestResult::Ok(())
+            },
+        )
+        .covers::<CreateGuildRequest>()
+        .covers::<DeleteGuildRequest>()
+        .covers::<CreateChannelRequest>()
+        .covers::<SendMessageRequest>()
+        .covers::<QueryHasPermissionRequest>();
+
+    suite
+        .test(
+            "outsider errors",
+            &[users::OUTSIDER, fixtures::GUILD],
+            &[Tag::Chat],
+            move |deps| async move {
+                let outsider = deps.get::<Client>(users::OUTSIDER);
+                let guild = deps.get::<GuildFixture>(fixtures::GUILD);
+                // reading the guild itself is checked by "outsider can't read guild"
+                check_err!(
+                    call!(outsider, GetGuildMembersRequest::new(guild.guild_id)).await,
+                    in data.refusal("not-joined")
+                );
+                check_err!(
+                    call!(
+                        outsider,
+                        SendMessage::new(guild.guild_id, guild.channel_id).text("hi")
+                    )
+                    .await,
+                    in data.refusal("not-joined")
+                );
+                check_err!(
+                    call!(outsider, DeleteGuildRequest::new(guild.guild_id)).await,
+                    in data.refusal("not-joined")
+                );
+                TestResult::Ok(())
+            },
+        )
+        .covers::<GetGuildMembersRequest>()
+        .covers::<SendMessageRequest>()
+        .covers::<DeleteGuildRequest>();
+
+    suite
+        .test(
+            "member permission errors",
+            &["member joins guild", users::CHANNEL, fixtures::GUILD],
+            &[Tag::Chat],
+            move |deps| async move {
+                let owner = deps.get::<Client>("client connection");
+                let member = deps.get::<Client>(users::MEMBER);
+                let guild = deps.get::<GuildFixture>(fixtures::GUILD);
+                let channel = deps.get::<ChannelFixture>(users::CHANNEL);
+                let message_id = call!(
+                    owner,
+                    SendMessage::new(guild.guild_id, channel.channel_id).text("mine")
+                )
+                .await?
+                .message_id;
+                check_err!(
+                    call!(
+                        member,
+                        UpdateMessageTextRequest {
+                            guild_id: guild.guild_id,
+                            channel_id: channel.channel_id,
+                            message_id,
+                            new_content: Some(
+                                FormattedText::default().with_text("theirs".to_string())
+                            ),
+                        }
+                    )
+                    .await,
+                    in data.refusal("not-enough-permissions")
+                );
+                // deleting the guild is checked by "member can't delete guild"
+                TestResult::Ok(())
+            },
+        )
+        .covers::<SendMessageRequest>()
+        .covers::<UpdateMessageTextRequest>();
+}
@@ -0,0 +1,256 @@
+//! Tests of the events the target streams to the subscribers of a guild.
+
+use harmony_rust_sdk::{
+    api::chat::{self, stream_event, *},
+    client::{
+        api::chat::{
+            channel::{CreateChannel, DeleteChannel},
+            guild::{CreateGuild, UpdateGuildInformation},
+            message::SendMessage,
+        },
+        Client, EventsSocket,
+    },
+};
+
+use crate::{
+    call, called, check,
+    expect::Expect,
+    fixtures::{self, GuildFixture},
+    plan::Tag,
+    suite::{Suite, TestResult},
+};
+
+/// How many times [`subscribe`] types before giving up on the subscription.
+const SUBSCRIBE_PROBES: usize = 3;
+
+/// Adds the tests checking that changes to a guild are sent to its subscribers.
+pub fn add(suite: &mut Suite) {
+    suite
+        .test(
+            "message events",
+            &["client auth", fixtures::GUILD],
+            &[Tag::Chat, Tag::Destructive],
+            |deps| async move {
+                let client = deps.get::<Client>("client connection");
+                let guild = deps.get::<GuildFixture>(fixtures::GUILD);
+                let mut events = subscribe(&client, guild.guild_id, guild.channel_id).await?;
+
+                let text = crate::random_string();
+                let message_id = call!(
+                    client,
+                    SendMessage::new(guild.guild_id, guild.channel_id).text(&text)
+                )
+                .await?
+                .message_id;
+                let sent = events
+                    .expect("sent message event", |event| match chat_event(event)? {
+                        stream_event::Event::SentMessage(sent) if sent.message_id == message_id => {
+                            Some(sent.clone())
+                        }
+                        _ => None,
+                    })
+                    .await?;
+                check!(sent.guild_id, guild.guild_id);
+                check!(sent.channel_id, guild.channel_id);
+                check!(
+                    sent.message.as_ref().and_then(|m| m.text()),
+                    Some(text.as_str())
+                );
+
+                let new_text = crate::random_string();
+                call!(
+                    client,
+                    UpdateMessageTextRequest {
+                        guild_id: guild.guild_id,
+                        channel_id: guild.channel_id,
+                        message_id,
+                        new_content: Some(FormattedText::default().with_text(new_text.clone())),
+                    }
+                )
+                .await?;
+                let edited = events
+                    .expect("edited message event", |event| match chat_event(event)? {
+                        stream_event::Event::EditedMessage(edited)
+                            if edited.message_id == message_id =>
+                        {
+                            Some(edited.clone())
+                        }
+                        _ => None,
+                    })
+                    .await?;
+                check!(edited.guild_id, guild.guild_id);
+                check!(edited.channel_id, guild.channel_id);
+                check!(
+                    edited.new_content.as_ref().map(|c| c.text.as_str()),
+                    Some(new_text.as_str())
+                );
+
+                call!(
+                    client,
+                    DeleteMessageRequest {
+                        guild_id: guild.guild_id,
+                        channel_id: guild.channel_id,
+                        message_id,
+                    }
+                )
+                .await?;
+                let deleted = events
+                    .expect("deleted message event", |event| match chat_event(event)? {
+                        stream_event::Event::DeletedMessage(deleted)
+                            if deleted.message_id == message_id =>
+                        {
+                            Some(deleted.clone())
+                        }
+                        _ => None,
+                    })
+                    .await?;
+                check!(deleted.guild_id, guild.guild_id);
+                check!(deleted.channel_id, guild.channel_id);
+
+                TestResult::Ok(deleted)
+            },
+        )
+        .covers::<StreamEventsRequest>()
+        .covers::<SendMessageRequest>()
+        .covers::<UpdateMessageTextRequest>()
+        .covers::<DeleteMessageRequest>();
+
+    suite
+        .test(
+            "channel events",
+            &["client auth", fixtures::GUILD],
+            &[Tag::Chat, Tag::Destructive],
+            |deps| async move {
+                let client = deps.get::<Client>("client connection");
+                let guild = deps.get::<GuildFixture>(fixtures::GUILD);
+                let mut events = subscribe(&client, guild.guild_id, guild.channel_id).await?;
+
+                let name = crate::random_string();
+                let channel_id = call!(client, CreateChannel::new(guild.guild_id, name.clone()))
+                    .await?
+                    .channel_id;
+                let created = events
+                    .expect("created channel event", |event| match chat_event(event)? {
+                        stream_event::Event::CreatedChannel(created)
+                            if created.channel_id == channel_id =>
+                        {
+                            Some(created.clone())
+                        }
+                        _ => None,
+                    })
+                    .await?;
+                check!(created.guild_id, guild.guild_id);
+                check!(created.name, name);
+
+                call!(client, DeleteChannel::new(guild.guild_id, channel_id)).await?;
+                let deleted = events
+                    .expect("deleted channel event", |event| match chat_event(event)? {
+                        stream_event::Event::DeletedChannel(deleted)
+                            if deleted.channel_id == channel_id =>
+                        {
+                            Some(deleted.clone())
+                        }
+                        _ => None,
+                    })
+                    .await?;
+                check!(deleted.guild_id, guild.guild_id);
+
+                TestResult::Ok(deleted)
+            },
+        )
+        .covers::<StreamEventsRequest>()
+        .covers::<CreateChannelRequest>()
+        .covers::<DeleteChannelRequest>();
+
+    // on a guild of its own, since other tests check the name of the provisioned one
+    suite
+        .test(
+            "guild update event",
+            &["client auth"],
+            &[Tag::Chat, Tag::Destructive],
+            |deps| async move {
+                let client = deps.get::<Client>("client connection");
+                let guild_id = call!(client, CreateGuild::new("event test".to_string()))
+                    .await?
+                    .guild_id;
+
+                let new_name = crate::random_string();
+                let updated = async {
+                    let channel_id =
+                        call!(client, CreateChannel::new(guild_id, "probe".to_string()))
+                            .await?
+                            .channel_id;
+                    let mut events = subscribe(&client, guild_id, channel_id).await?;
+                    call!(
+                        client,
+                        UpdateGuildInformation::new(guild_id).with_new_guild_name(new_name.clone()),
+                    )
+                    .await?;
+                    events
+                        .expect("edited guild event", |event| match chat_event(event)? {
+                            stream_event::Event::EditedGuild(updated)
+                                if updated.guild_id == guild_id =>
+                            {
+                                Some(updated.clone())
+                            }
+                            _ => None,
+                        })
+                        .await
+                }
+                .await;
+                call!(client, DeleteGuildRequest::new(guild_id)).await?;
+
+                let updated = updated?;
+                check!(updated.new_name.as_deref(), Some(new_name.as_str()));
+                TestResult::Ok(updated)
+            },
+        )
+        .covers::<StreamEventsRequest>()
+        .covers::<CreateGuildRequest>()
+        .covers::<UpdateGuildInformationRequest>()
+        .covers::<DeleteGuildRequest>();
+}
+
+/// Subscribes to the events of `guild_id`, returning once they arrive.
+///
+/// Targets may register a subscription after answering it, and would drop
+/// the events sent meanwhile, so typing in `channel_id` is repeated until
+/// its event comes back.
+async fn subscribe(
+    client: &Client,
+    guild_id: u64,
+    channel_id: u64,
+) -> TestResult<Expect<EventsSocket>> {
+    let mut events = Expect::new(
+        called!(
+            StreamEventsRequest,
+            client.subscribe_events(vec![EventSource::Guild(guild_id)])
+        )
+        .await?,
+    );
+    for _ in 0..SUBSCRIBE_PROBES {
+        call!(client, TypingRequest::new(guild_id, channel_id)).await?;
+        let typing = events
+            .expect("typing event", |event| match chat_event(event)? {
+                stream_event::Event::Typing(typing) if typing.channel_id == channel_id => Some(()),
+                _ => None,
+            })
+            .await;
+        if typing.is_ok() {
+            return Ok(events);
+        }
+    }
+    Err(format!(
+        "no typing event after typing {} times, the subscription never started",
+        SUBSCRIBE_PROBES
+    )
+    .into())
+}
+
+/// The chat event of `event`, if that's what it is.
+fn chat_event(event: &Event) -> Option<&stream_event::Event> {
+    match event {
+        Event::Chat(chat::StreamEvent { event }) => event.as_ref(),
+        _ => None,
+    }
+}
@@ -0,0 +1,141 @@
+//! Waiting for messages the target streams, such as events or auth steps.
+//!
+//! Messages that arrive while waiting for another one are kept, so tests
+//! can expect them in any order:
+//!
+//! ```ignore
+//! let mut events = Expect::new(client.subscribe_events(sources).await?);
+//! let sent = events.expect("sent message", |event| ...).await?;
+//! ```
+
+use std::{collections::VecDeque, fmt::Debug, future::Future, pin::Pin, time::Duration};
+
+use harmony_rust_sdk::{
+    api::{auth::AuthStep, chat::Event},
+    client::{error::ClientResult, AuthSocket, EventsSocket},
+};
+use tracing::debug;
+
+use crate::suite::TestResult;
+
+type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+/// A socket the target sends messages on.
+pub trait Source {
+    type Item: Debug;
+
+    /// Receives the next message, or none if the socket got something that
+    /// isn't one.
+    fn receive(&mut self) -> BoxFuture<'_, ClientResult<Option<Self::Item>>>;
+}
+
+impl Source for EventsSocket {
+    type Item = Event;
+
+    fn receive(&mut self) -> BoxFuture<'_, ClientResult<Option<Event>>> {
+        Box::pin(self.get_event())
+    }
+}
+
+impl Source for AuthSocket {
+    type Item = AuthStep;
+
+    fn receive(&mut self) -> BoxFuture<'_, ClientResult<Option<AuthStep>>> {
+        Box::pin(self.get_step())
+    }
+}
+
+/// Messages of a [`Source`], and the ones nobody expected yet.
+pub struct Expect<S: Source> {
+    source: S,
+    buffer: VecDeque<S::Item>,
+    timeout: Duration,
+}
+
+impl<S: Source> Expect<S> {
+    pub fn new(source: S) -> Self {
+        Self {
+            source,
+            buffer: VecDeque::new(),
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    /// Sets how long each expectation waits, 5 seconds by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Waits for the next message, whatever it is.
+    pub async fn next(&mut self) -> TestResult<S::Item> {
+        self.wait("message", |_| true).await
+    }
+
+    /// Returns what `matches` extracts from the first message it accepts,
+    /// among the kept messages then the ones received within the timeout.
+    ///
+    /// Fails listing every kept message if none is accepted in time.
+    pub async fn expect<T>(
+        &mut self,
+        what: &str,
+        mut matches: impl FnMut(&S::Item) -> Option<T>,
+    ) -> TestResult<T> {
+        let item = self.wait(what, |item| matches(item).is_some()).await?;
+        Ok(matches(&item).expect("accepted message matches"))
+    }
+
+    /// Waits for the source to end, with an error or something that isn't a
+    /// message, before the timeout and without sending any other message.
+    pub async fn closed(&mut self) -> TestResult<()> {
+        match tokio::time::timeout(self.timeout, self.source.receive()).await {
+            Ok(Ok(None)) | Ok(Err(_)) => Ok(()),
+            Ok(Ok(Some(item))) => Err(format!("received {:?} instead of the end", item).into()),
+            Err(_) => Err(format!("still open after {} secs", self.timeout.as_secs_f64()).into()),
+        }
+    }
+
+    async fn wait(
+        &mut self,
+        what: &str,
+        mut accept: impl FnMut(&S::Item) -> bool,
+    ) -> TestResult<S::Item> {
+        if let Some(index) = self.buffer.iter().position(&mut accept) {
+            return Ok(self.buffer.remove(index).unwrap());
+        }
+
+        let (source, buffer) = (&mut self.source, &mut self.buffer);
+        let wait = async {
+            loop {
+                let item = match source.receive().await? {
+                    Some(item) => item,
+                    None => continue,
+                };
+                debug!("received {:?}", item);
+                if accept(&item) {
+                    return TestResult::Ok(item);
+                }
+                buffer.push_back(item);
+            }
+        };
+        let result = tokio::time::timeout(self.timeout, wait).await;
+        match result {
+            Ok(result) => result,
+            Err(_) => {
+                let received = self
+                    .buffer
+                    .iter()
+                    .map(|item| format!("\n  {:?}", item))
+                    .collect::<String>();
+                Err(format!(
+                    "no {} within {} secs, received {} other messages:{}",
+                    what,
+                    self.timeout.as_secs_f64(),
+                    self.buffer.len(),
+                    received
+                )
+                .into())
+            }
+        }
+    }
+}
@@ -0,0 +1,588 @@
+//! An in-process Harmony server keeping everything in memory, so the suite
+//! can run without network access.
+//!
+//! It only implements what the suite calls, speaking the hRPC protocol
+//! directly: unary calls are protobuf encoded `POST` bodies, and streams are
+//! websockets carrying one protobuf message per binary frame.
+
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use harmony_rust_sdk::api::{
+    auth::*,
+    batch::*,
+    chat::{self, *},
+    emote::*,
+    exports::{
+        hrpc::exports::futures_util::{SinkExt, StreamExt},
+        prost::Message as _,
+    },
+    mediaproxy::*,
+    profile::*,
+    Endpoint,
+};
+use hyper::{
+    header::{
+        AUTHORIZATION, CONNECTION, CONTENT_DISPOSITION, CONTENT_TYPE, SEC_WEBSOCKET_ACCEPT,
+        SEC_WEBSOCKET_KEY, SEC_WEBSOCKET_PROTOCOL, UPGRADE,
+    },
+    service::{make_service_fn, service_fn},
+    upgrade::Upgraded,
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use tokio::sync::{
+    mpsc::{unbounded_channel, UnboundedSender},
+    oneshot,
+};
+use tokio_tungstenite::{
+    tungstenite::{handshake::derive_accept_key, protocol::Role, Message as WsMessage},
+    WebSocketStream,
+};
+use tracing::{debug, error};
+
+use crate::config::TestData;
+
+use self::state::{Caller, ServerError, ServerResult, State, StoredFile};
+
+mod state;
+
+/// Where `POST`ing a session token ends that session, which the protocol has
+/// no endpoint for.
+const REVOKE_PATH: &str = "/_fake/revoke";
+
+type Handler = Box<dyn Fn(&mut State, Caller, &[u8]) -> ServerResult<Vec<u8>> + Send + Sync>;
+
+struct Fake {
+    state: Mutex<State>,
+    routes: HashMap<&'static str, Handler>,
+}
+
+/// Starts an empty fake server on a random local port, returning the target
+/// to run the suite against.
+///
+/// The server accepts any registration, and sessions can be revoked.
+pub async fn spawn() -> Result<TestData, String> {
+    let fake = Arc::new(Fake {
+        state: Mutex::new(State::default()),
+        routes: routes(),
+    });
+
+    let make_service = make_service_fn(move |_| {
+        let fake = fake.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let fake = fake.clone();
+                async move { Ok::<_, Infallible>(fake.handle(request).await) }
+            }))
+        }
+    });
+    let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+    let server = Server::try_bind(&addr)
+        .map_err(|err| format!("failed to bind fake server: {}", err))?
+        .serve(make_service);
+    let url = format!("http://{}", server.local_addr());
+    tokio::spawn(async move {
+        if let Err(err) = server.await {
+            error!("fake server stopped: {}", err);
+        }
+    });
+
+    Ok(TestData {
+        name: "fake".to_string(),
+        server: url.clone(),
+        revoke_url: Some(format!("{}{}", url, REVOKE_PATH)),
+        name_res: url,
+        errors: [
+            ("bad-guild-name", "h.bad-guild-name"),
+            ("bad-channel-name", "h.bad-channel-name"),
+            ("bad-message-content", "h.bad-message-content"),
+            ("bad-permission", "h.bad-permission"),
+            ("not-joined", "h.not-joined"),
+            ("not-enough-permissions", "h.not-enough-permissions"),
+        ]
+        .iter()
+        .map(|(kind, identifier)| (kind.to_string(), identifier.to_string()))
+        .collect(),
+    })
+}
+
+fn routes() -> HashMap<&'static str, Handler> {
+    let mut routes = HashMap::new();
+    let mut add = |path: &'static str, handler: Handler| {
+        routes.insert(path, handler);
+    };
+
+    add(BeginAuthRequest::ENDPOINT_PATH, unary(State::begin_auth));
+    add(NextStepRequest::ENDPOINT_PATH, unary(State::next_step));
+    add(StepBackRequest::ENDPOINT_PATH, unary(State::step_back));
+    add(
+        CheckLoggedInRequest::ENDPOINT_PATH,
+        unary(State::check_logged_in),
+    );
+
+    add(
+        PreviewGuildRequest::ENDPOINT_PATH,
+        unary(State::preview_guild),
+    );
+    add(
+        CreateInviteRequest::ENDPOINT_PATH,
+        unary(State::create_invite),
+    );
+    add(JoinGuildRequest::ENDPOINT_PATH, unary(State::join_guild));
+    add(
+        GetGuildListRequest::ENDPOINT_PATH,
+        unary(State::get_guild_list),
+    );
+    add(GetGuildRequest::ENDPOINT_PATH, unary(State::get_guild));
+    add(
+        GetGuildRolesRequest::ENDPOINT_PATH,
+        unary(State::get_guild_roles),
+    );
+    add(
+        GetGuildMembersRequest::ENDPOINT_PATH,
+        unary(State::get_guild_members),
+    );
+    add(
+        GetGuildChannelsRequest::ENDPOINT_PATH,
+        unary(State::get_guild_channels),
+    );
+    add(
+        CreateGuildRequest::ENDPOINT_PATH,
+        unary(State::create_guild),
+    );
+    add(
+        UpdateGuildInformationRequest::ENDPOINT_PATH,
+        unary(State::update_guild_information),
+    );
+    add(
+        DeleteGuildRequest::ENDPOINT_PATH,
+        unary(State::delete_guild),
+    );
+    add(
+        CreateChannelRequest::ENDPOINT_PATH,
+        unary(State::create_channel),
+    );
+    add(
+        DeleteChannelRequest::ENDPOINT_PATH,
+        unary(State::delete_channel),
+    );
+    add(TypingRequest::ENDPOINT_PATH, unary(State::typing));
+    add(
+        SendMessageRequest::ENDPOINT_PATH,
+        unary(State::send_message),
+    );
+    add(
+        GetChannelMessagesRequest::ENDPOINT_PATH,
+        unary(State::get_channel_messages),
+    );
+    add(GetMessageRequest::ENDPOINT_PATH, unary(State::get_message));
+    add(
+        UpdateMessageTextRequest::ENDPOINT_PATH,
+        unary(State::update_message_text),
+    );
+    add(
+        DeleteMessageRequest::ENDPOINT_PATH,
+        unary(State::delete_message),
+    );
+    add(
+        QueryHasPermissionRequest::ENDPOINT_PATH,
+        unary(State::query_has_permission),
+    );
+
+    add(GetProfileRequest::ENDPOINT_PATH, unary(State::get_profile));
+    add(
+        UpdateProfileRequest::ENDPOINT_PATH,
+        unary(State::update_profile),
+    );
+
+    add(
+        GetEmotePacksRequest::ENDPOINT_PATH,
+        unary(State::get_emote_packs),
+    );
+
+    add(
+        InstantViewRequest::ENDPOINT_PATH,
+        unary(State::instant_view),
+    );
+    add(
+        CanInstantViewRequest::ENDPOINT_PATH,
+        unary(State::can_instant_view),
+    );
+    add(
+        FetchLinkMetadataRequest::ENDPOINT_PATH,
+        unary(State::fetch_link_metadata),
+    );
+
+    routes
+}
+
+/// Wraps a typed endpoint into a handler of raw protobuf messages.
+fn unary<Req, Res>(endpoint: fn(&mut State, Caller, Req) -> ServerResult<Res>) -> Handler
+where
+    Req: harmony_rust_sdk::api::exports::prost::Message + Default,
+    Res: harmony_rust_sdk::api::exports::prost::Message,
+{
+    Box::new(move |state, caller, body| {
+        let request = Req::decode(body)
+            .map_err(|err| ServerError::new("hrpc.invalid-request-payload", err.to_string()))?;
+        endpoint(state, caller, request).map(|response| response.encode_to_vec())
+    })
+}
+
+impl Fake {
+    async fn handle(self: Arc<Self>, request: Request<Body>) -> Response<Body> {
+        let path = request.uri().path().to_string();
+        debug!("fake server: {} {}", request.method(), path);
+
+        if request.headers().contains_key(SEC_WEBSOCKET_KEY) {
+            return self.upgrade(request, &path);
+        }
+        let method = request.method().clone();
+        match (&method, path.as_str()) {
+            (&Method::GET, "/_harmony/server") => {
+                let url = format!(
+                    "http://{}",
+                    request
+                        .headers()
+                        .get("host")
+                        .and_then(|host| host.to_str().ok())
+                        .unwrap_or_default()
+                );
+                json(serde_json::json!({ "h.server": url }))
+            }
+            (&Method::POST, REVOKE_PATH) => self.revoke(&request),
+            (&Method::POST, "/_harmony/media/upload") => self.upload(request).await,
+            (&Method::GET, path) if path.starts_with("/_harmony/media/download/") => {
+                self.download(&path["/_harmony/media/download/".len()..])
+            }
+            (&Method::POST, _) => self.unary(request, &path).await,
+            _ => status(StatusCode::NOT_FOUND),
+        }
+    }
+
+    fn caller(&self, request: &Request<Body>) -> Caller {
+        self.state.lock().unwrap().caller(token(request))
+    }
+
+    fn revoke(&self, request: &Request<Body>) -> Response<Body> {
+        match token(request) {
+            Some(token) if self.state.lock().unwrap().revoke(token) => status(StatusCode::OK),
+            _ => status(StatusCode::UNAUTHORIZED),
+        }
+    }
+
+    async fn unary(&self, request: Request<Body>, path: &str) -> Response<Body> {
+        let caller = self.caller(&request);
+        let body = match hyper::body::to_bytes(request.into_body()).await {
+            Ok(body) => body,
+            Err(_) => return status(StatusCode::BAD_REQUEST),
+        };
+        match self.call(caller, path, &body) {
+            Ok(response) => Response::builder()
+                .header(CONTENT_TYPE, crate::HRPC_CONTENT_TYPE)
+                .body(Body::from(response))
+                .unwrap(),
+            Err(err) => {
+                debug!("fake server: {} failed: {:?}", path, err);
+                let code = match err.identifier {
+                    "hrpc.not-found" => StatusCode::NOT_FOUND,
+                    "h.bad-session" => StatusCode::UNAUTHORIZED,
+                    _ => StatusCode::BAD_REQUEST,
+                };
+                Response::builder()
+                    .status(code)
+                    .header(CONTENT_TYPE, crate::HRPC_CONTENT_TYPE)
+                    .body(Body::from(err.into_proto().encode_to_vec()))
+                    .unwrap()
+            }
+        }
+    }
+
+    fn call(&self, caller: Caller, path: &str, body: &[u8]) -> ServerResult<Vec<u8>> {
+        if path == BatchRequest::ENDPOINT_PATH {
+            let request = BatchRequest::decode(body)
+                .map_err(|err| ServerError::new("hrpc.invalid-request-payload", err.to_string()))?;
+            let responses = request
+                .requests
+                .iter()
+                .map(|any| {
+                    self.call(caller, &any.endpoint, &any.request)
+                        .map(Into::into)
+                })
+                .collect::<ServerResult<_>>()?;
+            return Ok(BatchResponse { responses }.encode_to_vec());
+        }
+        if path == BatchSameRequest::ENDPOINT_PATH {
+            let request = BatchSameRequest::decode(body)
+                .map_err(|err| ServerError::new("hrpc.invalid-request-payload", err.to_string()))?;
+            let responses = request
+                .requests
+                .iter()
+                .map(|raw| self.call(caller, &request.endpoint, raw).map(Into::into))
+                .collect::<ServerResult<_>>()?;
+            return Ok(BatchSameResponse { responses }.encode_to_vec());
+        }
+
+        let handler = self.routes.get(path).ok_or_else(|| {
+            ServerError::new("hrpc.not-found", format!("{} is not implemented", path))
+        })?;
+        handler(&mut self.state.lock().unwrap(), caller, body)
+    }
+
+    fn upgrade(self: Arc<Self>, request: Request<Body>, path: &str) -> Response<Body> {
+        let events = match path {
+            StreamStepsRequest::ENDPOINT_PATH => false,
+            StreamEventsRequest::ENDPOINT_PATH => true,
+            _ => return status(StatusCode::NOT_FOUND),
+        };
+        let caller = self.caller(&request);
+        if events && caller.0.is_none() {
+            return status(StatusCode::UNAUTHORIZED);
+        }
+        let revoked = token(&request)
+            .filter(|_| events)
+            .map(|token| self.state.lock().unwrap().on_revoke(token));
+        let accept = derive_accept_key(request.headers()[SEC_WEBSOCKET_KEY].as_bytes());
+        let protocol = request.headers().get(SEC_WEBSOCKET_PROTOCOL).cloned();
+
+        tokio::spawn(async move {
+            match hyper::upgrade::on(request).await {
+                Ok(upgraded) => {
+                    let socket =
+                        WebSocketStream::from_raw_socket(upgraded, Role::Server, None).await;
+                    if events {
+                        // there is a session, or events were refused above
+                        self.stream_events(socket, caller, revoked.unwrap()).await;
+                    } else {
+                        self.stream_steps(socket).await;
+                    }
+                }
+                Err(err) => error!("fake server: websocket upgrade failed: {}", err),
+            }
+        });
+
+        let mut response = Response::builder()
+            .status(StatusCode::SWITCHING_PROTOCOLS)
+            .header(CONNECTION, "upgrade")
+            .header(UPGRADE, "websocket")
+            .header(SEC_WEBSOCKET_ACCEPT, accept);
+        if let Some(protocol) = protocol {
+            response = response.header(SEC_WEBSOCKET_PROTOCOL, protocol);
+        }
+        response.body(Body::empty()).unwrap()
+    }
+
+    /// Sends the steps of an auth flow, whose id is the first message the
+    /// client sends, until either side closes the socket.
+    async fn stream_steps(&self, mut socket: WebSocketStream<Upgraded>) {
+        let auth_id = match socket.next().await {
+            Some(Ok(WsMessage::Binary(raw))) => match StreamStepsRequest::decode(raw.as_slice()) {
+                Ok(request) => request.auth_id,
+                Err(err) => {
+                    error!("fake server: invalid stream steps request: {}", err);
+                    return;
+                }
+            },
+            _ => return,
+        };
+        let mut steps = match self.state.lock().unwrap().subscribe_steps(&auth_id) {
+            Ok(steps) => steps,
+            Err(err) => {
+                error!("fake server: can't stream steps: {:?}", err);
+                return;
+            }
+        };
+
+        loop {
+            tokio::select! {
+                step = steps.recv() => {
+                    let step = match step {
+                        Some(step) => step,
+                        None => break,
+                    };
+                    let response = StreamStepsResponse { step: Some(step) };
+                    if socket.send(WsMessage::Binary(response.encode_to_vec())).await.is_err() {
+                        break;
+                    }
+                }
+                message = socket.next() => match message {
+                    Some(Ok(WsMessage::Close(_))) | Some(Err(_)) | None => break,
+                    _ => {}
+                },
+            }
+        }
+    }
+
+    /// Sends the events of the guilds the client subscribes to, until either
+    /// side closes the socket or the session is revoked.
+    async fn stream_events(
+        &self,
+        mut socket: WebSocketStream<Upgraded>,
+        caller: Caller,
+        mut revoked: oneshot::Receiver<()>,
+    ) {
+        let (subscriber, mut events) = unbounded_channel();
+        loop {
+            tokio::select! {
+                _ = &mut revoked => {
+                    let _ = socket.close(None).await;
+                    break;
+                }
+                // the subscriber is kept here, so this never returns none
+                Some(event) = events.recv() => {
+                    let response = StreamEventsResponse {
+                        event: Some(stream_events_response::Event::Chat(chat::StreamEvent {
+                            event: Some(event),
+                        })),
+                    };
+                    if socket.send(WsMessage::Binary(response.encode_to_vec())).await.is_err() {
+                        break;
+                    }
+                }
+                message = socket.next() => match message {
+                    Some(Ok(WsMessage::Binary(raw))) => self.subscribe(caller, &raw, &subscriber),
+                    Some(Ok(WsMessage::Close(_))) | Some(Err(_)) | None => break,
+                    _ => {}
+                },
+            }
+        }
+    }
+
+    fn subscribe(
+        &self,
+        caller: Caller,
+        raw: &[u8],
+        subscriber: &UnboundedSender<chat::stream_event::Event>,
+    ) {
+        let request = match StreamEventsRequest::decode(raw) {
+            Ok(request) => request,
+            Err(err) => {
+                error!("fake server: invalid stream events request: {}", err);
+                return;
+            }
+        };
+        match request.request {
+            Some(stream_events_request::Request::SubscribeToGuild(guild)) => {
+                let subscribed = self.state.lock().unwrap().subscribe_guild(
+                    caller,
+                    guild.guild_id,
+                    subscriber.clone(),
+                );
+                if let Err(err) = subscribed {
+                    error!("fake server: can't subscribe to guild: {:?}", err);
+                }
+            }
+            other => debug!("fake server: ignoring subscription {:?}", other),
+        }
+    }
+
+    async fn upload(&self, request: Request<Body>) -> Response<Body> {
+        if self.caller(&request).0.is_none() {
+            return status(StatusCode::UNAUTHORIZED);
+        }
+        let boundary = request
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split("boundary=").nth(1))
+            .map(|boundary| boundary.trim_matches('"').to_string());
+        let body = hyper::body::to_bytes(request.into_body()).await;
+        let file = match (boundary, body) {
+            (Some(boundary), Ok(body)) => parse_multipart(&body, &boundary),
+            _ => None,
+        };
+        match file {
+            Some(file) => {
+                let id = self.state.lock().unwrap().add_file(file);
+                json(serde_json::json!({ "id": id }))
+            }
+            None => status(StatusCode::BAD_REQUEST),
+        }
+    }
+
+    fn download(&self, id: &str) -> Response<Body> {
+        // external files are proxied by real servers, pretend they're empty
+        if id.starts_with("http") {
+            return Response::builder()
+                .header(CONTENT_TYPE, "application/octet-stream")
+                .body(Body::empty())
+                .unwrap();
+        }
+        let state = self.state.lock().unwrap();
+        match state.file(id) {
+            Some(file) => Response::builder()
+                .header(CONTENT_TYPE, file.content_type.as_str())
+                .header(
+                    CONTENT_DISPOSITION,
+                    format!("inline; filename=\"{}\"", file.name),
+                )
+                .body(Body::from(file.data.clone()))
+                .unwrap(),
+            None => status(StatusCode::NOT_FOUND),
+        }
+    }
+}
+
+/// The session token `request` was sent with.
+fn token(request: &Request<Body>) -> Option<&str> {
+    request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|token| token.to_str().ok())
+}
+
+/// Extracts the first file of a `multipart/form-data` body.
+fn parse_multipart(body: &[u8], boundary: &str) -> Option<StoredFile> {
+    let delimiter = format!("--{}", boundary);
+    let start = find(body, delimiter.as_bytes())? + delimiter.len();
+    let part = &body[start..];
+    let headers_end = find(part, b"\r\n\r\n")?;
+    let headers = String::from_utf8_lossy(&part[..headers_end]);
+    let data = &part[headers_end + 4..];
+    let data = &data[..find(data, format!("\r\n{}", delimiter).as_bytes())?];
+
+    let mut name = String::new();
+    let mut content_type = "application/octet-stream".to_string();
+    for line in headers.lines() {
+        let (key, value) = match line.split_once(':') {
+            Some(header) => header,
+            None => continue,
+        };
+        if key.eq_ignore_ascii_case("content-type") {
+            content_type = value.trim().to_string();
+        } else if key.eq_ignore_ascii_case("content-disposition") {
+            if let Some(filename) = value.split("filename=").nth(1) {
+                name = filename.trim().trim_matches('"').to_string();
+            }
+        }
+    }
+    Some(StoredFile {
+        name,
+        content_type,
+        data: data.to_vec(),
+    })
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn json(value: serde_json::Value) -> Response<Body> {
+    Response::builder()
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(value.to_string()))
+        .unwrap()
+}
+
+fn status(code: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(code)
+        .body(Body::empty())
+        .unwrap()
+}
@@ -0,0 +1,1022 @@
+use std::collections::HashMap;
+
+use harmony_rust_sdk::api::{
+    auth::*,
+    chat::{
+        stream_event::{self, Event as ChatEvent},
+        *,
+    },
+    emote::*,
+    exports::hrpc::proto::Error as HrpcError,
+    mediaproxy::*,
+    profile::*,
+};
+use tokio::sync::{
+    mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    oneshot,
+};
+
+/// An error returned to the client, with the identifier a real server would use.
+#[derive(Debug)]
+pub struct ServerError {
+    pub identifier: &'static str,
+    pub message: String,
+}
+
+impl ServerError {
+    pub fn new(identifier: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            identifier,
+            message: message.into(),
+        }
+    }
+
+    pub fn into_proto(self) -> HrpcError {
+        HrpcError {
+            identifier: self.identifier.to_string(),
+            human_message: self.message,
+            ..Default::default()
+        }
+    }
+}
+
+pub type ServerResult<T> = Result<T, ServerError>;
+
+/// Who is making a request, if they sent a valid session token.
+#[derive(Debug, Clone, Copy)]
+pub struct Caller(pub Option<u64>);
+
+impl Caller {
+    fn user(self) -> ServerResult<u64> {
+        self.0
+            .ok_or_else(|| ServerError::new("h.bad-session", "invalid or missing session token"))
+    }
+}
+
+struct User {
+    email: String,
+    password: String,
+    name: String,
+    status: i32,
+    is_bot: bool,
+}
+
+struct StoredMessage {
+    author_id: u64,
+    created_at: u64,
+    edited_at: Option<u64>,
+    content: Option<Content>,
+}
+
+struct StoredChannel {
+    name: String,
+    kind: i32,
+    /// Messages by id, ids only ever increase so this is in sending order.
+    messages: Vec<(u64, StoredMessage)>,
+}
+
+struct StoredGuild {
+    name: String,
+    owner: Option<u64>,
+    members: Vec<u64>,
+    channels: Vec<(u64, StoredChannel)>,
+}
+
+pub struct StoredFile {
+    pub name: String,
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, Copy)]
+enum AuthStage {
+    Initial,
+    Login,
+    Register,
+    Done,
+}
+
+/// An auth flow started by `BeginAuth`.
+struct AuthFlow {
+    stage: AuthStage,
+    subscribers: Vec<UnboundedSender<AuthStep>>,
+    /// Steps nobody was subscribed to yet, sent to the first subscriber.
+    backlog: Vec<AuthStep>,
+}
+
+impl AuthFlow {
+    fn push(&mut self, step: AuthStep) {
+        self.subscribers
+            .retain(|subscriber| subscriber.send(step.clone()).is_ok());
+        if self.subscribers.is_empty() {
+            self.backlog.push(step);
+        }
+    }
+}
+
+/// In-memory state of the fake server.
+#[derive(Default)]
+pub struct State {
+    next_id: u64,
+    users: HashMap<u64, User>,
+    sessions: HashMap<String, u64>,
+    auth_flows: HashMap<String, AuthFlow>,
+    guilds: HashMap<u64, StoredGuild>,
+    invites: HashMap<String, u64>,
+    files: HashMap<String, StoredFile>,
+    /// Event streams subscribed to each guild.
+    subscribers: HashMap<u64, Vec<UnboundedSender<ChatEvent>>>,
+    /// Event streams to close when each session is revoked.
+    streams: HashMap<String, Vec<oneshot::Sender<()>>>,
+}
+
+impl State {
+    fn id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    pub fn caller(&self, token: Option<&str>) -> Caller {
+        Caller(token.and_then(|token| self.sessions.get(token).copied()))
+    }
+
+    /// Ends the session `token`, closing its event streams.
+    ///
+    /// Returns whether there was such a session.
+    pub fn revoke(&mut self, token: &str) -> bool {
+        for stream in self.streams.remove(token).unwrap_or_default() {
+            let _ = stream.send(());
+        }
+        self.sessions.remove(token).is_some()
+    }
+
+    /// Returns a receiver notified when the session `token` is revoked.
+    pub fn on_revoke(&mut self, token: &str) -> oneshot::Receiver<()> {
+        let (tx, rx) = oneshot::channel();
+        self.streams.entry(token.to_string()).or_default().push(tx);
+        rx
+    }
+
+    pub fn add_file(&mut self, file: StoredFile) -> String {
+        let id = crate::random_string();
+        self.files.insert(id.clone(), file);
+        id
+    }
+
+    pub fn file(&self, id: &str) -> Option<&StoredFile> {
+        self.files.get(id)
+    }
+
+    /// Returns a receiver for the steps of the auth flow `auth_id`.
+    pub fn subscribe_steps(&mut self, auth_id: &str) -> ServerResult<UnboundedReceiver<AuthStep>> {
+        let flow = self.auth_flow(auth_id)?;
+        let (tx, rx) = unbounded_channel();
+        for step in flow.backlog.drain(..) {
+            let _ = tx.send(step);
+        }
+        flow.subscribers.push(tx);
+        Ok(rx)
+    }
+
+    /// Sends the events of `guild_id` to `subscriber` from now on.
+    pub fn subscribe_guild(
+        &mut self,
+        caller: Caller,
+        guild_id: u64,
+        subscriber: UnboundedSender<ChatEvent>,
+    ) -> ServerResult<()> {
+        self.guild(caller, guild_id)?;
+        self.subscribers
+            .entry(guild_id)
+            .or_default()
+            .push(subscriber);
+        Ok(())
+    }
+
+    fn broadcast(&mut self, guild_id: u64, event: ChatEvent) {
+        if let Some(subscribers) = self.subscribers.get_mut(&guild_id) {
+            subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+        }
+    }
+
+    fn auth_flow(&mut self, auth_id: &str) -> ServerResult<&mut AuthFlow> {
+        self.auth_flows
+            .get_mut(auth_id)
+            .ok_or_else(|| ServerError::new("h.bad-auth-id", "unknown auth id"))
+    }
+
+    fn guild(&self, caller: Caller, guild_id: u64) -> ServerResult<&StoredGuild> {
+        let user = caller.user()?;
+        let guild = self
+            .guilds
+            .get(&guild_id)
+            .ok_or_else(|| ServerError::new("h.bad-guild-id", "unknown guild"))?;
+        if guild.members.contains(&user) {
+            Ok(guild)
+        } else {
+            Err(ServerError::new(
+                "h.not-joined",
+                "not a member of the guild",
+            ))
+        }
+    }
+
+    fn guild_mut(&mut self, caller: Caller, guild_id: u64) -> ServerResult<&mut StoredGuild> {
+        self.guild(caller, guild_id)?;
+        Ok(self.guilds.get_mut(&guild_id).unwrap())
+    }
+
+    fn channel_mut(
+        &mut self,
+        caller: Caller,
+        guild_id: u64,
+        channel_id: u64,
+    ) -> ServerResult<&mut StoredChannel> {
+        self.guild_mut(caller, guild_id)?
+            .channels
+            .iter_mut()
+            .find(|(id, _)| *id == channel_id)
+            .map(|(_, channel)| channel)
+            .ok_or_else(|| ServerError::new("h.bad-channel-id", "unknown channel"))
+    }
+
+    fn message_mut(
+        &mut self,
+        caller: Caller,
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+    ) -> ServerResult<&mut StoredMessage> {
+        self.channel_mut(caller, guild_id, channel_id)?
+            .messages
+            .iter_mut()
+            .find(|(id, _)| *id == message_id)
+            .map(|(_, message)| message)
+            .ok_or_else(|| ServerError::new("h.bad-message-id", "unknown message"))
+    }
+
+    fn user_mut(&mut self, caller: Caller) -> ServerResult<&mut User> {
+        let user = caller.user()?;
+        self.users
+            .get_mut(&user)
+            .ok_or_else(|| ServerError::new("h.bad-session", "user no longer exists"))
+    }
+}
+
+// auth
+
+impl State {
+    pub fn begin_auth(
+        &mut self,
+        _: Caller,
+        _: BeginAuthRequest,
+    ) -> ServerResult<BeginAuthResponse> {
+        let auth_id = crate::random_string();
+        self.auth_flows.insert(
+            auth_id.clone(),
+            AuthFlow {
+                stage: AuthStage::Initial,
+                subscribers: Vec::new(),
+                backlog: Vec::new(),
+            },
+        );
+        Ok(BeginAuthResponse { auth_id })
+    }
+
+    pub fn next_step(
+        &mut self,
+        _: Caller,
+        request: NextStepRequest,
+    ) -> ServerResult<NextStepResponse> {
+        let stage = self.auth_flow(&request.auth_id)?.stage;
+        let (stage, step) = match (stage, request.step) {
+            (AuthStage::Initial, None) => (AuthStage::Initial, choice_step()),
+            (AuthStage::Initial, Some(next_step_request::Step::Choice(choice))) => {
+                match choice.choice.as_str() {
+                    "login" => (
+                        AuthStage::Login,
+                        form_step("login", &[("email", "email"), ("password", "password")]),
+                    ),
+                    "register" => (
+                        AuthStage::Register,
+                        form_step(
+                            "register",
+                            &[
+                                ("email", "email"),
+                                ("username", "text"),
+                                ("password", "new-password"),
+                            ],
+                        ),
+                    ),
+                    other => {
+                        return Err(ServerError::new(
+                            "h.bad-auth-choice",
+                            format!("unknown choice {}", other),
+                        ))
+                    }
+                }
+            }
+            (AuthStage::Login, Some(next_step_request::Step::Form(form))) => {
+                let fields = form_values(form);
+                let (email, password) = match fields.as_slice() {
+                    [email, password] => (email, password),
+                    _ => return Err(ServerError::new("h.bad-auth-form", "expected 2 fields")),
+                };
+                let user_id = self
+                    .users
+                    .iter()
+                    .find(|(_, user)| &user.email == email && &user.password == password)
+                    .map(|(id, _)| *id)
+                    .ok_or_else(|| {
+                        ServerError::new("h.wrong-user-or-password", "wrong email or password")
+                    })?;
+                (AuthStage::Done, self.session_step(user_id))
+            }
+            (AuthStage::Register, Some(next_step_request::Step::Form(form))) => {
+                let fields = form_values(form);
+                let (email, name, password) = match fields.as_slice() {
+                    [email, name, password] => (email, name, password),
+                    _ => return Err(ServerError::new("h.bad-auth-form", "expected 3 fields")),
+                };
+                if self.users.values().any(|user| &user.email == email) {
+                    return Err(ServerError::new("h.email-in-use", "email is already used"));
+                }
+                let user_id = self.id();
+                self.users.insert(
+                    user_id,
+                    User {
+                        email: email.clone(),
+                        password: password.clone(),
+                        name: name.clone(),
+                        status: 0,
+                        is_bot: false,
+                    },
+                );
+                (AuthStage::Done, self.session_step(user_id))
+            }
+            _ => {
+                return Err(ServerError::new(
+                    "h.bad-auth-step",
+                    "step does not match the current stage",
+                ))
+            }
+        };
+        let flow = self.auth_flow(&request.auth_id)?;
+        flow.stage = stage;
+        flow.push(step.clone());
+        Ok(NextStepResponse { step: Some(step) })
+    }
+
+    pub fn step_back(
+        &mut self,
+        _: Caller,
+        request: StepBackRequest,
+    ) -> ServerResult<StepBackResponse> {
+        let flow = self.auth_flow(&request.auth_id)?;
+        match flow.stage {
+            AuthStage::Login | AuthStage::Register => {}
+            _ => {
+                return Err(ServerError::new(
+                    "h.cant-go-back",
+                    "the current step has nothing before it",
+                ))
+            }
+        }
+        let step = choice_step();
+        flow.stage = AuthStage::Initial;
+        flow.push(step.clone());
+        Ok(StepBackResponse { step: Some(step) })
+    }
+
+    fn session_step(&mut self, user_id: u64) -> AuthStep {
+        let session_token = crate::random_string();
+        self.sessions.insert(session_token.clone(), user_id);
+        AuthStep {
+            step: Some(auth_step::Step::Session(Session {
+                user_id,
+                session_token,
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+    }
+
+    pub fn check_logged_in(
+        &mut self,
+        caller: Caller,
+        _: CheckLoggedInRequest,
+    ) -> ServerResult<CheckLoggedInResponse> {
+        caller.user()?;
+        Ok(CheckLoggedInResponse {})
+    }
+}
+
+fn choice_step() -> AuthStep {
+    AuthStep {
+        step: Some(auth_step::Step::Choice(auth_step::Choice {
+            title: "initial".to_string(),
+            options: vec!["login".to_string(), "register".to_string()],
+        })),
+        ..Default::default()
+    }
+}
+
+fn form_step(title: &str, fields: &[(&str, &str)]) -> AuthStep {
+    AuthStep {
+        can_go_back: true,
+        step: Some(auth_step::Step::Form(auth_step::Form {
+            title: title.to_string(),
+            fields: fields
+                .iter()
+                .map(|(name, r#type)| auth_step::form::FormField {
+                    name: name.to_string(),
+                    r#type: r#type.to_string(),
+                })
+                .collect(),
+        })),
+        ..Default::default()
+    }
+}
+
+fn form_values(form: next_step_request::Form) -> Vec<String> {
+    form.fields
+        .into_iter()
+        .map(|field| match field.field {
+            Some(next_step_request::form_fields::Field::String(value)) => value,
+            Some(next_step_request::form_fields::Field::Bytes(value)) => {
+                String::from_utf8_lossy(&value).into_owned()
+            }
+            Some(next_step_request::form_fields::Field::Number(value)) => value.to_string(),
+            None => String::new(),
+        })
+        .collect()
+}
+
+// chat
+
+impl State {
+    pub fn preview_guild(
+        &mut self,
+        _: Caller,
+        request: PreviewGuildRequest,
+    ) -> ServerResult<PreviewGuildResponse> {
+        let guild = self
+            .invites
+            .get(&request.invite_id)
+            .and_then(|id| self.guilds.get(id))
+            .ok_or_else(|| ServerError::new("h.bad-invite-id", "unknown invite"))?;
+        Ok(PreviewGuildResponse {
+            name: guild.name.clone(),
+            member_count: guild.members.len() as u64,
+            ..Default::default()
+        })
+    }
+
+    pub fn create_invite(
+        &mut self,
+        caller: Caller,
+        request: CreateInviteRequest,
+    ) -> ServerResult<CreateInviteResponse> {
+        self.guild(caller, request.guild_id)?;
+        if self.invites.contains_key(&request.name) {
+            return Err(ServerError::new("h.invite-exists", "invite already exists"));
+        }
+        self.invites.insert(request.name.clone(), request.guild_id);
+        Ok(CreateInviteResponse {
+            invite_id: request.name,
+        })
+    }
+
+    pub fn join_guild(
+        &mut self,
+        caller: Caller,
+        request: JoinGuildRequest,
+    ) -> ServerResult<JoinGuildResponse> {
+        let user = caller.user()?;
+        let guild_id = *self
+            .invites
+            .get(&request.invite_id)
+            .ok_or_else(|| ServerError::new("h.bad-invite-id", "unknown invite"))?;
+        let guild = self.guilds.get_mut(&guild_id).unwrap();
+        if guild.members.contains(&user) {
+            return Err(ServerError::new(
+                "h.already-in-guild",
+                "already a member of the guild",
+            ));
+        }
+        guild.members.push(user);
+        Ok(JoinGuildResponse { guild_id })
+    }
+
+    pub fn get_guild_list(
+        &mut self,
+        caller: Caller,
+        _: GetGuildListRequest,
+    ) -> ServerResult<GetGuildListResponse> {
+        let user = caller.user()?;
+        let mut guilds = self
+            .guilds
+            .iter()
+            .filter(|(_, guild)| guild.members.contains(&user))
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        guilds.sort_unstable();
+        Ok(GetGuildListResponse {
+            guilds: guilds
+                .into_iter()
+                .map(|guild_id| get_guild_list_response::GuildListEntry {
+                    guild_id,
+                    ..Default::default()
+                })
+                .collect(),
+        })
+    }
+
+    pub fn get_guild(
+        &mut self,
+        caller: Caller,
+        request: GetGuildRequest,
+    ) -> ServerResult<GetGuildResponse> {
+        let guild = self.guild(caller, request.guild_id)?;
+        Ok(GetGuildResponse {
+            guild: Some(Guild {
+                name: guild.name.clone(),
+                owner_ids: guild.owner.into_iter().collect(),
+                ..Default::default()
+            }),
+        })
+    }
+
+    pub fn get_guild_roles(
+        &mut self,
+        caller: Caller,
+        request: GetGuildRolesRequest,
+    ) -> ServerResult<GetGuildRolesResponse> {
+        self.guild(caller, request.guild_id)?;
+        Ok(GetGuildRolesResponse::default())
+    }
+
+    pub fn get_guild_members(
+        &mut self,
+        caller: Caller,
+        request: GetGuildMembersRequest,
+    ) -> ServerResult<GetGuildMembersResponse> {
+        let guild = self.guild(caller, request.guild_id)?;
+        Ok(GetGuildMembersResponse {
+            members: guild.members.clone(),
+        })
+    }
+
+    pub fn get_guild_channels(
+        &mut self,
+        caller: Caller,
+        request: GetGuildChannelsRequest,
+    ) -> ServerResult<GetGuildChannelsResponse> {
+        let guild = self.guild(caller, request.guild_id)?;
+        Ok(GetGuildChannelsResponse {
+            channels: guild
+                .channels
+                .iter()
+                .map(|(channel_id, channel)| ChannelWithId {
+                    channel_id: *channel_id,
+                    channel: Some(Channel {
+                        channel_name: channel.name.clone(),
+                        kind: channel.kind,
+                        ..Default::default()
+                    }),
+                })
+                .collect(),
+        })
+    }
+
+    pub fn create_guild(
+        &mut self,
+        caller: Caller,
+        request: CreateGuildRequest,
+    ) -> ServerResult<CreateGuildResponse> {
+        let user = caller.user()?;
+        if request.name.is_empty() {
+            return Err(ServerError::new("h.bad-guild-name", "guild name is empty"));
+        }
+        let guild_id = self.id();
+        let channel_id = self.id();
+        self.guilds.insert(
+            guild_id,
+            StoredGuild {
+                name: request.name,
+                owner: Some(user),
+                members: vec![user],
+                channels: vec![(
+                    channel_id,
+                    StoredChannel {
+                        name: "general".to_string(),
+                        kind: 0,
+                        messages: Vec::new(),
+                    },
+                )],
+            },
+        );
+        Ok(CreateGuildResponse { guild_id })
+    }
+
+    pub fn update_guild_information(
+        &mut self,
+        caller: Caller,
+        request: UpdateGuildInformationRequest,
+    ) -> ServerResult<UpdateGuildInformationResponse> {
+        let guild = self.guild_mut(caller, request.guild_id)?;
+        if let Some(name) = &request.new_name {
+            guild.name = name.clone();
+        }
+        self.broadcast(
+            request.guild_id,
+            ChatEvent::EditedGuild(stream_event::GuildUpdated {
+                guild_id: request.guild_id,
+                new_name: request.new_name,
+                ..Default::default()
+            }),
+        );
+        Ok(UpdateGuildInformationResponse {})
+    }
+
+    pub fn delete_guild(
+        &mut self,
+        caller: Caller,
+        request: DeleteGuildRequest,
+    ) -> ServerResult<DeleteGuildResponse> {
+        let guild = self.guild(caller, request.guild_id)?;
+        if guild.owner != caller.0 {
+            return Err(ServerError::new(
+                "h.not-enough-permissions",
+                "only the owner can delete a guild",
+            ));
+        }
+        self.guilds.remove(&request.guild_id);
+        self.invites.retain(|_, guild| *guild != request.guild_id);
+        self.broadcast(
+            request.guild_id,
+            ChatEvent::DeletedGuild(stream_event::GuildDeleted {
+                guild_id: request.guild_id,
+            }),
+        );
+        self.subscribers.remove(&request.guild_id);
+        Ok(DeleteGuildResponse {})
+    }
+
+    pub fn create_channel(
+        &mut self,
+        caller: Caller,
+        request: CreateChannelRequest,
+    ) -> ServerResult<CreateChannelResponse> {
+        self.guild(caller, request.guild_id)?;
+        if request.channel_name.is_empty() {
+            return Err(ServerError::new(
+                "h.bad-channel-name",
+                "channel name is empty",
+            ));
+        }
+        let channel_id = self.id();
+        self.guild_mut(caller, request.guild_id)?.channels.push((
+            channel_id,
+            StoredChannel {
+                name: request.channel_name.clone(),
+                kind: request.kind,
+                messages: Vec::new(),
+            },
+        ));
+        self.broadcast(
+            request.guild_id,
+            ChatEvent::CreatedChannel(stream_event::ChannelCreated {
+                guild_id: request.guild_id,
+                channel_id,
+                name: request.channel_name,
+                kind: request.kind,
+                ..Default::default()
+            }),
+        );
+        Ok(CreateChannelResponse { channel_id })
+    }
+
+    pub fn delete_channel(
+        &mut self,
+        caller: Caller,
+        request: DeleteChannelRequest,
+    ) -> ServerResult<DeleteChannelResponse> {
+        let guild = self.guild_mut(caller, request.guild_id)?;
+        let before = guild.channels.len();
+        guild.channels.retain(|(id, _)| *id != request.channel_id);
+        if guild.channels.len() == before {
+            return Err(ServerError::new("h.bad-channel-id", "unknown channel"));
+        }
+        self.broadcast(
+            request.guild_id,
+            ChatEvent::DeletedChannel(stream_event::ChannelDeleted {
+                guild_id: request.guild_id,
+                channel_id: request.channel_id,
+            }),
+        );
+        Ok(DeleteChannelResponse {})
+    }
+
+    pub fn typing(
+        &mut self,
+        caller: Caller,
+        request: TypingRequest,
+    ) -> ServerResult<TypingResponse> {
+        let user_id = caller.user()?;
+        self.channel_mut(caller, request.guild_id, request.channel_id)?;
+        self.broadcast(
+            request.guild_id,
+            ChatEvent::Typing(stream_event::Typing {
+                user_id,
+                guild_id: request.guild_id,
+                channel_id: request.channel_id,
+            }),
+        );
+        Ok(TypingResponse {})
+    }
+
+    pub fn send_message(
+        &mut self,
+        caller: Caller,
+        request: SendMessageRequest,
+    ) -> ServerResult<SendMessageResponse> {
+        let author_id = caller.user()?;
+        self.channel_mut(caller, request.guild_id, request.channel_id)?;
+        if is_blank(&request.content) {
+            return Err(ServerError::new(
+                "h.bad-message-content",
+                "message has no content",
+            ));
+        }
+        let message_id = self.id();
+        let message = StoredMessage {
+            author_id,
+            created_at: now(),
+            edited_at: None,
+            content: request.content,
+        };
+        let sent = message.to_proto();
+        self.channel_mut(caller, request.guild_id, request.channel_id)?
+            .messages
+            .push((message_id, message));
+        self.broadcast(
+            request.guild_id,
+            ChatEvent::SentMessage(stream_event::MessageSent {
+                echo_id: request.echo_id,
+                guild_id: request.guild_id,
+                channel_id: request.channel_id,
+                message_id,
+                message: Some(sent),
+            }),
+        );
+        Ok(SendMessageResponse { message_id })
+    }
+
+    pub fn get_channel_messages(
+        &mut self,
+        caller: Caller,
+        request: GetChannelMessagesRequest,
+    ) -> ServerResult<GetChannelMessagesResponse> {
+        let channel = self.channel_mut(caller, request.guild_id, request.channel_id)?;
+        Ok(GetChannelMessagesResponse {
+            // newest first, like a real server
+            messages: channel
+                .messages
+                .iter()
+                .rev()
+                .map(|(message_id, message)| MessageWithId {
+                    message_id: *message_id,
+                    message: Some(message.to_proto()),
+                })
+                .collect(),
+            reached_top: true,
+        })
+    }
+
+    pub fn get_message(
+        &mut self,
+        caller: Caller,
+        request: GetMessageRequest,
+    ) -> ServerResult<GetMessageResponse> {
+        let message = self.message_mut(
+            caller,
+            request.guild_id,
+            request.channel_id,
+            request.message_id,
+        )?;
+        Ok(GetMessageResponse {
+            message: Some(message.to_proto()),
+        })
+    }
+
+    pub fn update_message_text(
+        &mut self,
+        caller: Caller,
+        request: UpdateMessageTextRequest,
+    ) -> ServerResult<UpdateMessageTextResponse> {
+        let user = caller.user()?;
+        let message = self.message_mut(
+            caller,
+            request.guild_id,
+            request.channel_id,
+            request.message_id,
+        )?;
+        if message.author_id != user {
+            return Err(ServerError::new(
+                "h.not-enough-permissions",
+                "only the author can edit a message",
+            ));
+        }
+        let edited_at = now();
+        message.content = Some(Content {
+            content: Some(content::Content::TextMessage(content::TextContent {
+                content: request.new_content.clone(),
+            })),
+        });
+        message.edited_at = Some(edited_at);
+        self.broadcast(
+            request.guild_id,
+            ChatEvent::EditedMessage(stream_event::MessageUpdated {
+                guild_id: request.guild_id,
+                channel_id: request.channel_id,
+                message_id: request.message_id,
+                edited_at,
+                new_content: request.new_content,
+            }),
+        );
+        Ok(UpdateMessageTextResponse {})
+    }
+
+    pub fn delete_message(
+        &mut self,
+        caller: Caller,
+        request: DeleteMessageRequest,
+    ) -> ServerResult<DeleteMessageResponse> {
+        let user = caller.user()?;
+        let channel = self.channel_mut(caller, request.guild_id, request.channel_id)?;
+        let index = channel
+            .messages
+            .iter()
+            .position(|(id, _)| *id == request.message_id)
+            .ok_or_else(|| ServerError::new("h.bad-message-id", "unknown message"))?;
+        if channel.messages[index].1.author_id != user {
+            return Err(ServerError::new(
+                "h.not-enough-permissions",
+                "only the author can delete a message",
+            ));
+        }
+        channel.messages.remove(index);
+        self.broadcast(
+            request.guild_id,
+            ChatEvent::DeletedMessage(stream_event::MessageDeleted {
+                guild_id: request.guild_id,
+                channel_id: request.channel_id,
+                message_id: request.message_id,
+            }),
+        );
+        Ok(DeleteMessageResponse {})
+    }
+
+    pub fn query_has_permission(
+        &mut self,
+        caller: Caller,
+        request: QueryHasPermissionRequest,
+    ) -> ServerResult<QueryHasPermissionResponse> {
+        self.guild(caller, request.guild_id)?;
+        let valid = request
+            .check_for
+            .split('.')
+            .all(|part| part == "*" || (!part.is_empty() && part.chars().all(is_node_char)));
+        if !valid {
+            return Err(ServerError::new(
+                "h.bad-permission",
+                format!("invalid permission {:?}", request.check_for),
+            ));
+        }
+        // members can do everything on the fake server
+        Ok(QueryHasPermissionResponse { ok: true })
+    }
+}
+
+/// Whether `content` has nothing to show.
+fn is_blank(content: &Option<Content>) -> bool {
+    match content
+        .as_ref()
+        .and_then(|content| content.content.as_ref())
+    {
+        Some(content::Content::TextMessage(text)) => match &text.content {
+            Some(text) => text.text.is_empty(),
+            None => true,
+        },
+        Some(_) => false,
+        None => true,
+    }
+}
+
+/// Whether `c` can be part of a permission node.
+fn is_node_char(c: char) -> bool {
+    c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_'
+}
+
+impl StoredMessage {
+    fn to_proto(&self) -> Message {
+        Message {
+            author_id: self.author_id,
+            created_at: self.created_at,
+            edited_at: self.edited_at,
+            content: self.content.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+// profile
+
+impl State {
+    pub fn get_profile(
+        &mut self,
+        caller: Caller,
+        request: GetProfileRequest,
+    ) -> ServerResult<GetProfileResponse> {
+        caller.user()?;
+        let user = self
+            .users
+            .get(&request.user_id)
+            .ok_or_else(|| ServerError::new("h.bad-user-id", "unknown user"))?;
+        Ok(GetProfileResponse {
+            profile: Some(Profile {
+                user_name: user.name.clone(),
+                user_status: user.status,
+                is_bot: user.is_bot,
+                ..Default::default()
+            }),
+        })
+    }
+
+    pub fn update_profile(
+        &mut self,
+        caller: Caller,
+        request: UpdateProfileRequest,
+    ) -> ServerResult<UpdateProfileResponse> {
+        let user = self.user_mut(caller)?;
+        if let Some(name) = request.new_user_name {
+            user.name = name;
+        }
+        if let Some(status) = request.new_user_status {
+            user.status = status;
+        }
+        if let Some(is_bot) = request.new_is_bot {
+            user.is_bot = is_bot;
+        }
+        Ok(UpdateProfileResponse {})
+    }
+}
+
+// emote
+
+impl State {
+    pub fn get_emote_packs(
+        &mut self,
+        caller: Caller,
+        _: GetEmotePacksRequest,
+    ) -> ServerResult<GetEmotePacksResponse> {
+        caller.user()?;
+        Ok(GetEmotePacksResponse::default())
+    }
+}
+
+// mediaproxy, never fetches anything so the suite stays offline
+
+impl State {
+    pub fn instant_view(
+        &mut self,
+        caller: Caller,
+        _: InstantViewRequest,
+    ) -> ServerResult<InstantViewResponse> {
+        caller.user()?;
+        Ok(InstantViewResponse::default())
+    }
+
+    pub fn can_instant_view(
+        &mut self,
+        caller: Caller,
+        _: CanInstantViewRequest,
+    ) -> ServerResult<CanInstantViewResponse> {
+        caller.user()?;
+        Ok(CanInstantViewResponse::default())
+    }
+
+    pub fn fetch_link_metadata(
+        &mut self,
+        caller: Caller,
+        _: FetchLinkMetadataRequest,
+    ) -> ServerResult<FetchLinkMetadataResponse> {
+        caller.user()?;
+        Ok(FetchLinkMetadataResponse::default())
+    }
+}
+
+fn now() -> u64 {
+    std::time::UNIX_EPOCH.elapsed().unwrap().as_secs()
+}
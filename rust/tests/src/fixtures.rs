@@ -0,0 +1,129 @@
+//! Guild and file the tests work with, created on the target at the start of
+//! the run so the suite doesn't depend on what already exists there.
+
+use harmony_rust_sdk::{
+    api::chat::*,
+    client::{
+        api::{
+            chat::{channel::CreateChannel, guild::CreateGuild},
+            rest,
+        },
+        error::ClientResult,
+        Client,
+    },
+};
+
+use crate::{
+    call,
+    plan::Tag,
+    suite::{Suite, TestResult},
+    CONTENT_TYPE, FILENAME, FILE_DATA,
+};
+
+/// Name of the test creating the [`GuildFixture`].
+pub const GUILD: &str = "provision guild";
+/// Name of the test uploading the [`FileFixture`].
+pub const FILE: &str = "provision file";
+
+#[derive(Debug)]
+pub struct GuildFixture {
+    pub guild_id: u64,
+    pub channel_id: u64,
+    /// Invite to the guild.
+    pub invite: String,
+}
+
+/// A channel of the provisioned guild that only some tests post in, so they
+/// don't see the messages of every other test.
+#[derive(Debug)]
+pub struct ChannelFixture {
+    pub channel_id: u64,
+}
+
+/// A file containing [`FILE_DATA`].
+#[derive(Debug)]
+pub struct FileFixture {
+    pub file_id: String,
+}
+
+/// Adds the tests creating the fixtures, and deleting them once every test
+/// finished.
+///
+/// Uploaded files can't be deleted, so they're left on the target.
+pub fn provision(suite: &mut Suite) {
+    suite
+        .test(GUILD, &["client auth"], &[], |deps| async move {
+            let client = deps.get::<Client>("client connection");
+            let guild_id = call!(client, CreateGuild::new("integration tests".to_string()))
+                .await?
+                .guild_id;
+            let channel_id = call!(client, CreateChannel::new(guild_id, "tests".to_string()))
+                .await?
+                .channel_id;
+            let invite = crate::random_string();
+            call!(
+                client,
+                CreateInviteRequest {
+                    guild_id,
+                    name: invite.clone(),
+                    possible_uses: 0,
+                }
+            )
+            .await?;
+            ClientResult::Ok(GuildFixture {
+                guild_id,
+                channel_id,
+                invite,
+            })
+        })
+        .covers::<CreateGuildRequest>()
+        .covers::<CreateChannelRequest>()
+        .covers::<CreateInviteRequest>();
+
+    suite
+        .teardown(
+            "delete provisioned guild",
+            &[GUILD],
+            &[],
+            |deps| async move {
+                let client = deps.get::<Client>("client connection");
+                let guild = deps.get::<GuildFixture>(GUILD);
+                call!(client, DeleteGuildRequest::new(guild.guild_id)).await
+            },
+        )
+        .covers::<DeleteGuildRequest>();
+
+    suite.test(FILE, &["client auth"], &[Tag::Media], |deps| async move {
+        let client = deps.get::<Client>("client connection");
+        let response = rest::upload(
+            &client,
+            FILENAME.to_string(),
+            CONTENT_TYPE.to_string(),
+            FILE_DATA.as_bytes().to_vec(),
+        )
+        .await?;
+        let body = serde_json::from_str::<serde_json::Value>(&response.text().await?)?;
+        let file_id = body["id"]
+            .as_str()
+            .ok_or("upload response has no file id")?
+            .to_string();
+        TestResult::Ok(FileFixture { file_id })
+    });
+}
+
+/// Adds the test called `name`, creating a [`ChannelFixture`] in the
+/// provisioned guild.
+///
+/// It's deleted with the guild.
+pub fn channel(suite: &mut Suite, name: &'static str) {
+    suite
+        .test(name, &[GUILD], &[Tag::Chat], move |deps| async move {
+            let client = deps.get::<Client>("client connection");
+            let guild = deps.get::<GuildFixture>(GUILD);
+            let channel_id = call!(client, CreateChannel::new(guild.guild_id, name.to_string()))
+                .await?
+                .channel_id;
+            ClientResult::Ok(ChannelFixture { channel_id })
+        })
+        .covers::<CreateChannelRequest>();
+}
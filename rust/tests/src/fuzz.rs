@@ -0,0 +1,416 @@
+//! Fuzzing the target with random and mutated protobuf bodies, looking for
+//! inputs it fails on instead of refusing them.
+//!
+//! Bodies are `POST`ed to every endpoint directly, with the tester's session.
+//! Inputs the target answers with a 5xx status, doesn't answer in time, or
+//! drops the connection on are minimized and saved, to be sent again with
+//! `--replay`.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    ffi::OsStr,
+    fmt,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use harmony_rust_sdk::api::{
+    auth::{NextStepRequest, StreamStepsRequest},
+    chat::{
+        FormattedText, GetChannelMessagesRequest, GetGuildMembersRequest, GetGuildRequest,
+        GetMessageRequest, PreviewGuildRequest, StreamEventsRequest, UpdateMessageTextRequest,
+    },
+    exports::{
+        hrpc::{encode::encode_protobuf_message, proto::Error as HrpcError},
+        prost::Message,
+    },
+    profile::GetProfileRequest,
+    Endpoint,
+};
+use hyper::{
+    client::HttpConnector,
+    header::{AUTHORIZATION, CONTENT_TYPE},
+    Body, Request,
+};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use rand::{prelude::*, rngs::StdRng};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, info_span, warn, Instrument};
+
+use crate::{
+    cli::Options,
+    config::{Config, TestData},
+    report::coverage::ENDPOINTS,
+    session,
+};
+
+/// How long the target may take to answer an input.
+const HANG_TIMEOUT: Duration = Duration::from_secs(10);
+/// How many inputs minimizing a failing input may send, fewer for hangs since
+/// each of them waits for the whole timeout.
+const MINIMIZE_ATTEMPTS: usize = 200;
+const MINIMIZE_HANG_ATTEMPTS: usize = 10;
+/// Longest random body.
+const MAX_RANDOM_LEN: usize = 64;
+
+/// Endpoints that aren't fuzzed, since streams are websockets rather than
+/// `POST` bodies.
+const SKIPPED: &[&str] = &[
+    StreamStepsRequest::ENDPOINT_PATH,
+    StreamEventsRequest::ENDPOINT_PATH,
+];
+
+/// How the target failed on an input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Failure {
+    Internal,
+    Hang,
+    Dropped,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Failure::Internal => "server error",
+            Failure::Hang => "hang",
+            Failure::Dropped => "dropped connection",
+        })
+    }
+}
+
+/// Where inputs are sent.
+struct Target {
+    http: hyper::Client<HttpsConnector<HttpConnector>>,
+    server: String,
+    /// Session token of the tester.
+    token: String,
+}
+
+/// A saved input the target failed on.
+#[derive(Debug, Serialize, Deserialize)]
+struct Crash {
+    endpoint: String,
+    failure: Failure,
+    error: String,
+    body: Vec<u8>,
+}
+
+impl Crash {
+    fn load(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+        serde_json::from_str(&content)
+            .map_err(|err| format!("invalid crash {}: {}", path.display(), err))
+    }
+
+    /// Saves the crash in `dir`, named after its endpoint and body so the
+    /// same input is only saved once.
+    fn save(&self, dir: &Path) -> Result<PathBuf, String> {
+        std::fs::create_dir_all(dir)
+            .map_err(|err| format!("failed to create {}: {}", dir.display(), err))?;
+        let mut hasher = DefaultHasher::new();
+        (&self.endpoint, &self.body).hash(&mut hasher);
+        let name = self
+            .endpoint
+            .trim_start_matches('/')
+            .replace(|c: char| !c.is_ascii_alphanumeric(), "-");
+        let path = dir.join(format!("{}-{:016x}.json", name, hasher.finish()));
+        let content = serde_json::to_string_pretty(self).expect("crashes are always valid json");
+        std::fs::write(&path, content)
+            .map_err(|err| format!("failed to write {}: {}", path.display(), err))?;
+        Ok(path)
+    }
+}
+
+/// Fuzzes and replays saved inputs against every target, as asked by
+/// `options`. Returns whether the targets failed on any input.
+pub async fn run(
+    config: &'static Config,
+    targets: &[&'static TestData],
+    options: &Options,
+) -> bool {
+    let mut failed = false;
+    for data in targets.iter().copied() {
+        let fuzzed = async {
+            let cached = session::cached(config, data, &config.email);
+            let client =
+                match session::login(config, data, &config.email, "rust_sdk_test", cached).await {
+                    Ok((client, _)) => client,
+                    Err(err) => {
+                        error!("can't authenticate: {}", err);
+                        return true;
+                    }
+                };
+            let target = Target {
+                http: hyper::Client::builder().build(
+                    HttpsConnectorBuilder::new()
+                        .with_native_roots()
+                        .https_or_http()
+                        .enable_http1()
+                        .build(),
+                ),
+                server: data.server.trim_end_matches('/').to_string(),
+                token: client
+                    .auth_status()
+                    .session()
+                    .map(|session| session.session_token.clone())
+                    .unwrap_or_default(),
+            };
+            let mut failed = false;
+            if !options.replay.is_empty() {
+                failed |= replay(&target, &options.replay).await;
+            }
+            if let Some(inputs) = options.fuzz {
+                let seed = options.fuzz_seed.unwrap_or_else(rand::random);
+                failed |= fuzz(&target, inputs, seed, &options.fuzz_dir).await;
+            }
+            failed
+        };
+        failed |= fuzzed
+            .instrument(info_span!("target", name = %data.name))
+            .await;
+    }
+    failed
+}
+
+/// Sends `inputs` generated bodies to every endpoint, saving the ones the
+/// target fails on in `dir`.
+async fn fuzz(target: &Target, inputs: u32, seed: u64, dir: &Path) -> bool {
+    info!("fuzzing with seed {}, pass --fuzz-seed to repeat it", seed);
+    let mut rng = StdRng::seed_from_u64(seed);
+    let seeds = seeds();
+    let mut found = 0;
+    for endpoint in ENDPOINTS.iter().filter(|path| !SKIPPED.contains(*path)) {
+        // every endpoint decodes an empty body as its default request
+        let mut endpoint_seeds = vec![Vec::new()];
+        endpoint_seeds.extend(
+            seeds
+                .iter()
+                .filter(|(path, _)| path == endpoint)
+                .map(|(_, body)| body.clone()),
+        );
+        for _ in 0..inputs {
+            let body = if rng.gen_ratio(1, 4) {
+                let len = rng.gen_range(0..=MAX_RANDOM_LEN);
+                (0..len).map(|_| rng.gen()).collect()
+            } else {
+                let mut body = endpoint_seeds.choose(&mut rng).unwrap().clone();
+                mutate(&mut rng, &mut body);
+                body
+            };
+            let (failure, _) = match send(target, endpoint, &body).await {
+                Some(failed) => failed,
+                None => continue,
+            };
+            warn!("{}: {} on {:?}, minimizing", endpoint, failure, body);
+            let body = minimize(target, endpoint, body, failure).await;
+            // the error of the minimized input, in case it differs
+            let error = match send(target, endpoint, &body).await {
+                Some((_, error)) => error,
+                None => "not reproduced after minimizing".to_string(),
+            };
+            let crash = Crash {
+                endpoint: endpoint.to_string(),
+                failure,
+                error,
+                body,
+            };
+            match crash.save(dir) {
+                Ok(path) => error!(
+                    "{}: {} on {:?}, saved to {}",
+                    endpoint,
+                    failure,
+                    crash.body,
+                    path.display()
+                ),
+                Err(err) => error!("{}: {} on {:?}: {}", endpoint, failure, crash.body, err),
+            }
+            found += 1;
+        }
+    }
+    info!(
+        "sent {} inputs to each endpoint, the target failed on {}",
+        inputs, found
+    );
+    found > 0
+}
+
+/// Sends the crashes saved at `paths` again, either files or directories of
+/// them. Returns whether the target still fails on any.
+async fn replay(target: &Target, paths: &[PathBuf]) -> bool {
+    let mut files = Vec::new();
+    for path in paths {
+        match std::fs::read_dir(path) {
+            Ok(entries) => {
+                let mut entries = entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| path.extension() == Some(OsStr::new("json")))
+                    .collect::<Vec<_>>();
+                entries.sort();
+                files.extend(entries);
+            }
+            Err(_) => files.push(path.clone()),
+        }
+    }
+
+    let mut failing = 0;
+    for path in &files {
+        let crash = match Crash::load(path) {
+            Ok(crash) => crash,
+            Err(err) => {
+                error!("{}", err);
+                failing += 1;
+                continue;
+            }
+        };
+        match send(target, &crash.endpoint, &crash.body).await {
+            Some((failure, error)) => {
+                error!(
+                    "{}: {} still fails with {}: {}",
+                    path.display(),
+                    crash.endpoint,
+                    failure,
+                    error
+                );
+                failing += 1;
+            }
+            None => info!("{}: {} is fixed", path.display(), crash.endpoint),
+        }
+    }
+    info!(
+        "replayed {} inputs, the target still fails on {}",
+        files.len(),
+        failing
+    );
+    failing > 0
+}
+
+/// Sends `body` to `endpoint`, returning how the target failed on it if it
+/// did rather than answering or refusing it.
+async fn send(target: &Target, endpoint: &str, body: &[u8]) -> Option<(Failure, String)> {
+    let request = Request::post(format!("{}{}", target.server, endpoint))
+        .header(CONTENT_TYPE, crate::HRPC_CONTENT_TYPE)
+        .header(AUTHORIZATION, target.token.as_str())
+        .body(Body::from(body.to_vec()))
+        .expect("endpoint paths are valid uris");
+    let call = async {
+        let response = target.http.request(request).await?;
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        Ok::<_, hyper::Error>((status, body))
+    };
+    let (status, body) = match tokio::time::timeout(HANG_TIMEOUT, call).await {
+        Ok(Ok(response)) => response,
+        Ok(Err(err)) => return Some((Failure::Dropped, err.to_string())),
+        Err(_) => {
+            return Some((
+                Failure::Hang,
+                format!("no answer within {} secs", HANG_TIMEOUT.as_secs_f64()),
+            ))
+        }
+    };
+    if !status.is_server_error() {
+        return None;
+    }
+    let error = match HrpcError::decode(body.as_ref()) {
+        Ok(err) if !err.identifier.is_empty() => {
+            format!("{} {}: {}", status, err.identifier, err.human_message)
+        }
+        _ => format!("{} {}", status, String::from_utf8_lossy(&body)),
+    };
+    Some((Failure::Internal, error))
+}
+
+/// Removes parts of `body` as long as the target still fails the same way.
+async fn minimize(target: &Target, endpoint: &str, mut body: Vec<u8>, failure: Failure) -> Vec<u8> {
+    let max_attempts = match failure {
+        Failure::Hang => MINIMIZE_HANG_ATTEMPTS,
+        _ => MINIMIZE_ATTEMPTS,
+    };
+    let mut attempts = 0;
+    let mut chunk = body.len() / 2;
+    while chunk > 0 && attempts < max_attempts {
+        let mut start = 0;
+        while start < body.len() && attempts < max_attempts {
+            let mut candidate = body.clone();
+            candidate.drain(start..(start + chunk).min(body.len()));
+            attempts += 1;
+            match send(target, endpoint, &candidate).await {
+                Some((failed, _)) if failed == failure => body = candidate,
+                _ => start += chunk,
+            }
+        }
+        chunk /= 2;
+    }
+    body
+}
+
+/// Applies a few random changes to `body`.
+fn mutate(rng: &mut StdRng, body: &mut Vec<u8>) {
+    for _ in 0..rng.gen_range(1..=4) {
+        match rng.gen_range(0..6) {
+            0 if !body.is_empty() => {
+                let i = rng.gen_range(0..body.len());
+                body[i] ^= 1 << rng.gen_range(0..8);
+            }
+            1 if !body.is_empty() => {
+                body.remove(rng.gen_range(0..body.len()));
+            }
+            2 if !body.is_empty() => body.truncate(rng.gen_range(0..body.len())),
+            // a field of any number and wire type, with random content
+            3 => {
+                let key = (rng.gen_range(1u8..16) << 3) | rng.gen_range(0..8);
+                body.push(key);
+                let len = rng.gen_range(0..8);
+                body.extend((0..len).map(|_| rng.gen::<u8>()));
+            }
+            // a length delimited field longer than the body
+            4 => {
+                body.push((rng.gen_range(1u8..16) << 3) | 2);
+                body.extend([0xff, 0xff, 0xff, 0xff, 0x0f]);
+            }
+            // a varint too long to fit in 64 bits
+            _ => {
+                body.push(rng.gen_range(1u8..16) << 3);
+                body.extend([0xff; 10]);
+                body.push(0x01);
+            }
+        }
+    }
+}
+
+fn seed<E: Endpoint + Message>(request: E) -> (&'static str, Vec<u8>) {
+    (E::ENDPOINT_PATH, encode_protobuf_message(&request).to_vec())
+}
+
+/// Valid requests to mutate, so inputs get past the first fields.
+fn seeds() -> Vec<(&'static str, Vec<u8>)> {
+    vec![
+        seed(NextStepRequest {
+            auth_id: "fuzz".to_string(),
+            ..Default::default()
+        }),
+        seed(GetGuildRequest::new(1)),
+        seed(GetGuildMembersRequest::new(1)),
+        seed(PreviewGuildRequest::new("fuzz".to_string())),
+        seed(GetChannelMessagesRequest {
+            guild_id: 1,
+            channel_id: 1,
+            ..Default::default()
+        }),
+        seed(GetMessageRequest {
+            guild_id: 1,
+            channel_id: 1,
+            message_id: 1,
+        }),
+        seed(UpdateMessageTextRequest {
+            guild_id: 1,
+            channel_id: 1,
+            message_id: 1,
+            new_content: Some(FormattedText::default().with_text("fuzz".to_string())),
+        }),
+        seed(GetProfileRequest::new(1)),
+    ]
+}
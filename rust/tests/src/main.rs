@@ -1,3 +1,8 @@
+use baseline::Baseline;
+use cli::Options;
+use config::{Config, TestData};
+use expect::Expect;
+use fixtures::{FileFixture, GuildFixture};
 use harmony_rust_sdk::{
     api::{
         auth::*, batch::*, chat::*, emote::*, exports::hrpc::encode::encode_protobuf_message,
@@ -5,7 +10,7 @@ use harmony_rust_sdk::{
     },
     client::{
         api::{
-            auth::*,
+            auth::AuthStepResponse,
             chat::{
                 channel::*,
                 guild::{CreateGuild, UpdateGuildInformation},
@@ -19,592 +24,1054 @@ use harmony_rust_sdk::{
         *,
     },
 };
+use plan::Tag;
 use rand::prelude::*;
-use tokio::time::Instant;
-use tracing::{error, info, info_span, Instrument, Level};
+use report::{json::JsonStream, tap::TapStream};
+use runner::TestRunner;
+use suite::{Suite, TestResult};
+use tracing::{error, info, info_span, warn, Instrument, Level};
 use tracing_subscriber::{prelude::*, util::SubscriberInitExt, EnvFilter};
 
-const RUNNING_IN_GH: bool = option_env!("CI").is_some();
+mod auth;
+mod baseline;
+mod check;
+mod cli;
+mod config;
+mod errors;
+mod events;
+mod expect;
+mod fake;
+mod fixtures;
+mod fuzz;
+mod plan;
+mod report;
+mod runner;
+mod session;
+mod suite;
+mod users;
 
-const EMAIL: &str = "rust_sdk_test@example.com";
-const PASSWORD: Option<&str> = option_env!("TESTER_PASSWORD");
+const RUNNING_IN_GH: bool = option_env!("CI").is_some();
 
 const FILE_DATA: &str = "They're waiting for you Gordon, in the test chamber.";
 const FILENAME: &str = "test_chamber.txt";
 const CONTENT_TYPE: &str = "text/plain";
-const EXTERNAL_URL: &str =
-    "https://cdn.discordapp.com/attachments/855956335689728010/855957272039260210/32b13e7ff8cb6b271db2c51aa9d6bcfb94250c7a8554c3e91fc1a9b64607ee9e.png";
+const HRPC_CONTENT_TYPE: &str = "application/hrpc";
 
-const INSTANT_VIEW_URL: &str = "https://duckduckgo.com/";
+#[tokio::main]
+async fn main() {
+    let options = Options::from_env().unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        std::process::exit(2)
+    });
+
+    let mut config = Config::load(options.config.as_deref()).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        std::process::exit(2)
+    });
+    if options.fake {
+        let fake = fake::spawn().await.unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            std::process::exit(2)
+        });
+        config.targets = vec![fake];
+        // the fake server accepts any registration
+        config
+            .password
+            .get_or_insert_with(|| "rust_sdk_test_password".to_string());
+        // its sessions don't outlive the run
+        config.session_cache = None;
+    }
+    let config: &'static Config = Box::leak(Box::new(config));
+
+    let targets = config
+        .targets
+        .iter()
+        .filter(|data| options.targets.is_empty() || options.targets.contains(&data.name))
+        .collect::<Vec<_>>();
+    if targets.is_empty() {
+        eprintln!("no targets selected");
+        std::process::exit(2);
+    }
 
-const SCHERZO_DATA: TestData = TestData {
-    server: "https://chat.harmonyapp.io:2289",
-    name_res: "https://chat.harmonyapp.io",
-    guild: 14467453680900551947,
-    channel: 5539953751079296767,
-    file_id: "murxMJmXb4qPd2HBRkkpVt",
-};
+    let planned = tests(config, targets[0]).plan();
+    let selected = options.selection.apply(&planned);
+    if options.list {
+        for test in planned.iter().filter(|test| selected.contains(&test.name)) {
+            let depth = plan::parents(&planned, test.name).count();
+            let tags = test.tags.iter().map(Tag::as_str).collect::<Vec<_>>();
+            println!("{}{} [{}]", "  ".repeat(depth), test.name, tags.join(", "));
+        }
+        return;
+    }
 
-static mut TESTS_COMPLETE: u16 = 0;
-static mut TESTS_TOTAL: u16 = 0;
-static mut TOTAL_TIME: Duration = Duration::ZERO;
-
-#[derive(Debug, Clone, Copy)]
-struct TestData {
-    server: &'static str,
-    name_res: &'static str,
-    guild: u64,
-    channel: u64,
-    file_id: &'static str,
-}
+    if let Err(err) = config.check_credentials() {
+        eprintln!("{}", err);
+        std::process::exit(2);
+    }
 
-#[tokio::main]
-async fn main() {
     let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::from("info"));
-    let logger = tracing_subscriber::fmt::layer();
+    let log_writer = || {
+        if options.reports_to_stdout() {
+            BoxMakeWriter::new(std::io::stderr)
+        } else {
+            BoxMakeWriter::new(std::io::stdout)
+        }
+    };
+    let logger = tracing_subscriber::fmt::layer().with_writer(log_writer());
 
     let reg = tracing_subscriber::registry().with(filter).with(logger);
 
     if RUNNING_IN_GH {
-        reg.with(tracing_subscriber::fmt::layer().event_format(GithubActionsFormatter))
-            .init()
+        reg.with(
+            tracing_subscriber::fmt::layer()
+                .event_format(GithubActionsFormatter)
+                .with_writer(log_writer()),
+        )
+        .init()
     } else {
         reg.init()
     }
 
-    unsafe {
-        TESTS_COMPLETE = 0;
-        TESTS_TOTAL = 0;
-        TOTAL_TIME = Duration::ZERO;
+    if options.fuzz.is_some() || !options.replay.is_empty() {
+        let failed = fuzz::run(config, &targets, &options).await;
+        std::process::exit(if failed { 1 } else { 0 });
     }
-    let s = tests(SCHERZO_DATA).instrument(info_span!("scherzo")).await;
-    let st = unsafe { TOTAL_TIME };
-
-    info!(
-        "Scherzo: {} tests successful, {} tests ran, completed in {} secs",
-        s,
-        unsafe { TESTS_TOTAL },
-        st.as_secs_f64()
-    );
-}
 
-async fn tests(data: TestData) -> u16 {
-    {
-        test(
-            "name resolution",
-            Client::new(data.name_res.parse().unwrap(), None),
-            |_| async {},
-        )
-        .await;
+    let mut runner = TestRunner::new().with_plan(
+        planned
+            .iter()
+            .filter(|test| selected.contains(&test.name))
+            .cloned()
+            .collect(),
+    );
+    if let Some(path) = &options.json {
+        match report::open(path) {
+            Ok(out) => runner = runner.with_listener(JsonStream::new(out)),
+            Err(err) => error!("failed to open {}: {}", path.display(), err),
+        }
+    }
+    if let Some(path) = &options.tap {
+        match report::open(path) {
+            Ok(out) => runner = runner.with_listener(TapStream::new(out)),
+            Err(err) => error!("failed to open {}: {}", path.display(), err),
+        }
+    }
+    // suites stop at the deadline and still run their teardowns, rather than
+    // being dropped with the guilds they provisioned
+    let deadline = options
+        .global_timeout
+        .map(|limit| tokio::time::Instant::now() + limit);
+    let mut run_timed_out = false;
+    'targets: for data in targets.iter().copied() {
+        for run in 1..=options.repeat {
+            if options.repeat > 1 {
+                info!("{}: run {} of {}", data.name, run, options.repeat);
+            }
+            let mut suite = tests(config, data);
+            suite.retain(&selected);
+            suite.policy(options.policy);
+            if let Some(deadline) = deadline {
+                suite.deadline(deadline);
+            }
+            run_timed_out = runner
+                .scope(&data.name, suite.run())
+                .instrument(info_span!("target", name = %data.name))
+                .await;
+            if run_timed_out {
+                break 'targets;
+            }
+        }
+    }
+    let summary = runner.finish();
+    if run_timed_out {
+        error!(
+            "run timed out after {} secs",
+            options.global_timeout.unwrap_or_default().as_secs_f64()
+        );
     }
 
-    test(
-        "client connection",
-        Client::new(data.server.parse().unwrap(), None),
-        |client| async move {
-            test(
-                "client auth",
-                async {
-                    async fn wait_for_socket(sock: &mut AuthSocket) {
-                        let fut = async move {
-                            loop {
-                                if let Ok(Some(a)) = sock.get_step().await {
-                                    tracing::info!("auth socket reply: {:?}", a);
-                                    break;
-                                }
-                            }
-                        };
-                        let dur = Duration::from_secs(5);
-
-                        tokio::time::timeout(dur, fut)
-                            .await
-                            .expect("did not receive auth step from stream");
-                    }
-
-                    let login = async {
-                        client.begin_auth().await?;
-                        let mut auth_sock = client.auth_stream().await?;
-
-                        client.next_auth_step(AuthStepResponse::Initial).await?;
-                        wait_for_socket(&mut auth_sock).await;
-
-                        client
-                            .next_auth_step(AuthStepResponse::login_choice())
-                            .await?;
-                        wait_for_socket(&mut auth_sock).await;
-
-                        client
-                            .next_auth_step(AuthStepResponse::login_form(
-                                EMAIL,
-                                PASSWORD.expect("no tester password?"),
-                            ))
-                            .await?;
-                        wait_for_socket(&mut auth_sock).await;
-
-                        ClientResult::Ok(())
-                    };
-
-                    if login.await.is_err() {
-                        client.begin_auth().await?;
-                        let mut auth_sock = client.auth_stream().await?;
-
-                        client.next_auth_step(AuthStepResponse::Initial).await?;
-                        wait_for_socket(&mut auth_sock).await;
-
-                        client
-                            .next_auth_step(AuthStepResponse::register_choice())
-                            .await?;
-                        wait_for_socket(&mut auth_sock).await;
-
-                        client
-                            .next_auth_step(AuthStepResponse::register_form(
-                                EMAIL,
-                                "rust_sdk_test",
-                                PASSWORD.expect("no tester password?"),
-                            ))
-                            .await?;
-                        wait_for_socket(&mut auth_sock).await;
-                    }
-
-                    ClientResult::Ok(())
-                },
-                |_a| async {
-                    check!(client.auth_status().is_authenticated(), true);
+    for data in &targets {
+        let summary = runner.summary_of(&data.name);
+        info!(
+            "{}: {} tests successful, {} failed, {} timed out, {} skipped, {} tests ran, {} never ran, completed in {} secs",
+            data.name,
+            summary.passed,
+            summary.failed,
+            summary.timed_out,
+            summary.skipped,
+            summary.total,
+            summary.not_run,
+            summary.total_time.as_secs_f64()
+        );
+    }
+    for record in runner.failures() {
+        error!(
+            "failed: {}: {}",
+            record.full_name(),
+            record.error.as_deref().unwrap_or_default()
+        );
+    }
+    for name in runner.not_run() {
+        warn!("never ran: {}", name);
+    }
 
-                    test(
-                        "check logged in",
-                        client.call(CheckLoggedInRequest::new()),
-                        |_| async {},
-                    )
-                    .await;
-                    let user_id = client.auth_status().session().unwrap().user_id;
+    if let Some(path) = options.junit {
+        let written =
+            report::open(&path).and_then(|out| report::junit::write(&runner.records(), out));
+        if let Err(err) = written {
+            error!(
+                "failed to write JUnit report to {}: {}",
+                path.display(),
+                err
+            );
+        }
+    }
 
-                    test_no_hand(
-                        "profile update",
-                        client.call(UpdateProfile::default().with_new_status(UserStatus::Online)),
-                    )
-                    .await;
+    if let Some(path) = options.html {
+        let written =
+            report::open(&path).and_then(|out| report::html::write(&runner.records(), out));
+        if let Err(err) = written {
+            error!("failed to write HTML report to {}: {}", path.display(), err);
+        }
+    }
 
-                    test_no_hand(
-                        "preview guild",
-                        client.call(PreviewGuildRequest::new("harmony".to_string())),
-                    )
-                    .await;
-
-                    test(
-                        "get guild list",
-                        client.call(GetGuildListRequest {}),
-                        |response| async move {
-                            check!(response.guilds.len(), 1);
-                        },
-                    )
-                    .await;
+    if let Some(path) = options.coverage {
+        let written = report::open(&path).and_then(|out| {
+            let plan = planned
+                .iter()
+                .filter(|test| selected.contains(&test.name))
+                .cloned()
+                .collect::<Vec<_>>();
+            report::coverage::write(&runner.calls(), &plan, out)
+        });
+        if let Err(err) = written {
+            error!(
+                "failed to write coverage report to {}: {}",
+                path.display(),
+                err
+            );
+        }
+    }
 
-                    test_no_hand(
-                        "get guild roles",
-                        client.call(GetGuildRolesRequest::new(data.guild)),
-                    )
-                    .await;
-
-                    test(
-                        "get guild members",
-                        client.call(GetGuildMembersRequest::new(data.guild)),
-                        |response| async {
-                            check!(response.members.len(), 1);
-
-                            test_no_hand(
-                                "get profile",
-                                client.call(GetProfileRequest::new(
-                                    *response
-                                        .members
-                                        .first()
-                                        .expect("expected at least one user in guild"),
-                                )),
-                            )
-                            .await;
-
-                            test_no_hand("get user bulk", {
-                                let requests = response
-                                    .members
-                                    .into_iter()
-                                    .map(|user_id| {
-                                        let req = GetProfileRequest::new(user_id);
-                                        let data = encode_protobuf_message(&req);
-                                        data.freeze()
-                                    })
-                                    .collect();
-                                client.call(BatchSameRequest::new(
-                                    GetProfileRequest::ENDPOINT_PATH.to_string(),
-                                    requests,
-                                ))
-                            })
-                            .await;
-                        },
-                    )
-                    .await;
+    if targets.len() > 1 {
+        let names = targets
+            .iter()
+            .map(|data| data.name.as_str())
+            .collect::<Vec<_>>();
+        if let Err(err) = report::matrix::write(&runner.records(), &names, std::io::stderr()) {
+            error!("failed to write comparison table: {}", err);
+        }
+    }
 
-                    test_no_hand("get emote packs", client.call(GetEmotePacksRequest {})).await;
+    if options.repeat > 1 {
+        if let Err(err) = report::latency::write(&runner.records(), std::io::stderr()) {
+            error!("failed to write latency statistics: {}", err);
+        }
+    }
 
-                    test_no_hand(
-                        "get guild channels",
-                        client.call(GetGuildChannelsRequest::new(data.guild)),
-                    )
-                    .await;
+    let current = Baseline::from_records(&runner.records());
+    let mut regressed = false;
+    if let Some(path) = &options.baseline {
+        match Baseline::load(path) {
+            Ok(baseline) => {
+                let comparison = baseline.compare(&current, options.regression_threshold);
+                comparison.log();
+                regressed = comparison.has_regressions();
+            }
+            Err(err) => error!("{}", err),
+        }
+    }
+    if let Some(path) = &options.save_baseline {
+        if let Err(err) = current.save(path) {
+            error!("{}", err);
+        }
+    }
 
-                    test_no_hand(
-                        "typing",
-                        client.call(TypingRequest::new(data.guild, data.channel)),
-                    )
-                    .await;
+    if summary.failed > 0 || summary.timed_out > 0 || run_timed_out || regressed {
+        std::process::exit(1);
+    }
+}
 
-                    let current_time = std::time::UNIX_EPOCH.elapsed().unwrap().as_secs();
-                    let msg = format!("test at {}", current_time);
-                    test_no_hand(
-                        "send message",
-                        client.call(SendMessage::new(data.guild, data.channel).text(&msg)),
+fn tests(config: &'static Config, data: &'static TestData) -> Suite {
+    let mut suite = Suite::new();
+
+    suite.test("name resolution", &[], &[], move |_| async move {
+        TestResult::Ok(Client::new(data.name_res.parse()?, None).await?)
+    });
+
+    // everything depends on it, so it rides out a flaky first connection even
+    // when --retries isn't set
+    suite
+        .test("client connection", &[], &[], move |_| async move {
+            let cached = session::cached(config, data, &config.email);
+            let (client, _) = session::connect(data, cached).await?;
+            TestResult::Ok(client)
+        })
+        .retries(2);
+
+    suite
+        .test(
+            "client auth",
+            &["client connection"],
+            &[Tag::Auth],
+            move |deps| async move {
+                let client = deps.get::<Client>("client connection");
+                let transcript =
+                    session::authenticate(config, data, &client, &config.email, "rust_sdk_test")
+                        .await?;
+
+                check!(client.auth_status().is_authenticated(), true);
+
+                TestResult::Ok(transcript)
+            },
+        )
+        .covers::<BeginAuthRequest>()
+        .covers::<StreamStepsRequest>()
+        .covers::<NextStepRequest>();
+
+    suite
+        .test(
+            "auth step back",
+            &["client connection"],
+            &[Tag::Auth],
+            move |_| async move {
+                let client = Client::new(data.server.parse()?, None).await?;
+                called!(BeginAuthRequest, client.begin_auth()).await?;
+                let mut steps =
+                    Expect::new(called!(StreamStepsRequest, client.auth_stream()).await?);
+                called!(
+                    NextStepRequest,
+                    client.next_auth_step(AuthStepResponse::Initial)
+                )
+                .await?;
+                let initial = steps.next().await?;
+                called!(
+                    NextStepRequest,
+                    client.next_auth_step(AuthStepResponse::login_choice())
+                )
+                .await?;
+                let login = steps.next().await?;
+                check!(login.can_go_back, true).hard()?;
+
+                called!(StepBackRequest, client.prev_auth_step()).await?;
+                let back = steps.next().await?;
+                check!(back, initial);
+                TestResult::Ok(back)
+            },
+        )
+        .covers::<BeginAuthRequest>()
+        .covers::<StreamStepsRequest>()
+        .covers::<NextStepRequest>()
+        .covers::<StepBackRequest>();
+
+    fixtures::provision(&mut suite);
+    events::add(&mut suite);
+    session::add(&mut suite, config, data);
+    users::add(&mut suite, config, data);
+    errors::add(&mut suite, config, data);
+
+    suite
+        .test(
+            "check logged in",
+            &["client auth"],
+            &[Tag::Auth],
+            |deps| async move {
+                let client = deps.get::<Client>("client connection");
+                call!(client, CheckLoggedInRequest::new()).await
+            },
+        )
+        .covers::<CheckLoggedInRequest>();
+
+    suite
+        .test("profile update", &["client auth"], &[], |deps| async move {
+            let client = deps.get::<Client>("client connection");
+            call!(
+                client,
+                UpdateProfile::default().with_new_status(UserStatus::Online)
+            )
+            .await
+        })
+        .covers::<UpdateProfileRequest>();
+
+    suite
+        .test(
+            "preview guild",
+            &["client auth", fixtures::GUILD],
+            &[Tag::Chat],
+            |deps| async move {
+                let client = deps.get::<Client>("client connection");
+                let guild = deps.get::<GuildFixture>(fixtures::GUILD);
+                call!(client, PreviewGuildRequest::new(guild.invite.clone())).await
+            },
+        )
+        .covers::<PreviewGuildRequest>();
+
+    suite
+        .test(
+            "get guild list",
+            &["client auth", fixtures::GUILD],
+            &[Tag::Chat],
+            |deps| async move {
+                let client = deps.get::<Client>("client connection");
+                let guild = deps.get::<GuildFixture>(fixtures::GUILD);
+                let response = call!(client, GetGuildListRequest {}).await?;
+                let guild_ids = response
+                    .guilds
+                    .iter()
+                    .map(|entry| entry.guild_id)
+                    .collect::<Vec<_>>();
+                check_contains!(guild_ids, &guild.guild_id);
+                ClientResult::Ok(response)
+            },
+        )
+        .covers::<GetGuildListRequest>();
+
+    suite
+        .test(
+            "get guild roles",
+            &["client auth", fixtures::GUILD],
+            &[Tag::Chat],
+            move |deps| async move {
+                let client = deps.get::<Client>("client connection");
+                let guild = deps.get::<GuildFixture>(fixtures::GUILD);
+                call!(client, GetGuildRolesRequest::new(guild.guild_id)).await
+            },
+        )
+        .covers::<GetGuildRolesRequest>();
+
+    suite
+        .test(
+            "get guild members",
+            &["client auth", fixtures::GUILD],
+            &[Tag::Chat],
+            move |deps| async move {
+                let client = deps.get::<Client>("client connection");
+                let guild = deps.get::<GuildFixture>(fixtures::GUILD);
+                let user_id = user_id(&client)?;
+                let response = call!(client, GetGuildMembersRequest::new(guild.guild_id)).await?;
+                check_contains!(response.members, &user_id);
+                TestResult::Ok(response)
+            },
+        )
+        .covers::<GetGuildMembersRequest>();
+
+    suite
+        .test(
+            "get profile",
+            &["get guild members"],
+            &[],
+            |deps| async move {
+                let client = deps.get::<Client>("client connection");
+                let response = deps.get::<GetGuildMembersResponse>("get guild members");
+                let user_id = *response
+                    .members
+                    .first()
+                    .ok_or("expected at least one user in guild")?;
+                TestResult::Ok(call!(client, GetProfileRequest::new(user_id)).await?)
+            },
+        )
+        .covers::<GetProfileRequest>();
+
+    suite
+        .test(
+            "get user bulk",
+            &["get guild members"],
+            &[],
+            |deps| async move {
+                let client = deps.get::<Client>("client connection");
+                let response = deps.get::<GetGuildMembersResponse>("get guild members");
+                let requests = response
+                    .members
+                    .iter()
+                    .map(|user_id| {
+                        let req = GetProfileRequest::new(*user_id);
+                        let data = encode_protobuf_message(&req);
+                        data.freeze()
+                    })
+                    .collect();
+                call!(
+                    client,
+                    BatchSameRequest::new(GetProfileRequest::ENDPOINT_PATH.to_string(), requests,)
+                )
+                .await
+            },
+        )
+        .covers::<BatchSameRequest>()
+        .covers::<GetProfileRequest>();
+
+    suite
+        .test(
+            "get emote packs",
+            &["client auth"],
+            &[],
+            |deps| async move {
+                let client = deps.get::<Client>("client connection");
+                call!(client, GetEmotePacksRequest {}).await
+            },
+        )
+        .covers::<GetEmotePacksRequest>();
+
+    suite
+        .test(
+            "get guild channels",
+            &["client auth", fixtures::GUILD],
+            &[Tag::Chat],
+            move |deps| async move {
+                let client = deps.get::<Client>("client connection");
+                let guild = deps.get::<GuildFixture>(fixtures::GUILD);
+                call!(client, GetGuildChannelsRequest::new(guild.guild_id)).await
+            },
+        )
+        .covers::<GetGuildChannelsRequest>();
+
+    suite
+        .test(
+            "typing",
+            &["client auth", fixtures::GUILD],
+            &[Tag::Chat],
+            move |deps| async move {
+                let client = deps.get::<Client>("client connection");
+                let guild = deps.get::<GuildFixture>(fixtures::GUILD);
+                call!(client, TypingRequest::new(guild.guild_id, guild.channel_id)).await
+            },
+        )
+        .covers::<TypingRequest>();
+
+    let current_time = std::time::UNIX_EPOCH.elapsed().unwrap().as_secs();
+    let msg = format!("test at {}", current_time);
+    suite
+        .test(
+            "send message",
+            &["client auth", fixtures::GUILD],
+            &[Tag::Chat],
+            {
+                let msg = msg.clone();
+                move |deps| {
+                    let msg = msg.clone();
+                    async move {
+                        let client = deps.get::<Client>("client connection");
+                        let guild = deps.get::<GuildFixture>(fixtures::GUILD);
+                        call!(
+                            client,
+                            SendMessage::new(guild.guild_id, guild.channel_id).text(&msg)
+                        )
+                        .await
+                    }
+                }
+            },
+        )
+        .covers::<SendMessageRequest>();
+
+    suite
+        .test(
+            "get channel messages",
+            &["send message", fixtures::GUILD],
+            &[Tag::Chat],
+            move |deps| {
+                let msg = msg.clone();
+                async move {
+                    let client = deps.get::<Client>("client connection");
+                    let guild = deps.get::<GuildFixture>(fixtures::GUILD);
+                    let sent = deps.get::<SendMessageResponse>("send message");
+                    let response = call!(
+                        client,
+                        GetChannelMessages::new(guild.guild_id, guild.channel_id)
                     )
-                    .await;
-
-                    test(
-                        "get channel messages",
-                        client.call(GetChannelMessages::new(data.guild, data.channel)),
-                        |response| {
-                            let client = client.clone();
-                            async move {
-                                let our_msg = response.messages.first().unwrap();
-                                let (message_id, message) =
-                                    (our_msg.message_id, our_msg.message.as_ref().unwrap());
-                                check!(message.text(), Some(msg.as_str()));
-
-                                let new_content = rand::thread_rng()
-                                    .sample_iter(rand::distributions::Alphanumeric)
-                                    .take(16)
-                                    .map(|c| c as char)
-                                    .collect::<String>();
-
-                                test(
-                                    "edit message",
-                                    client.call(UpdateMessageTextRequest {
-                                        guild_id: data.guild,
-                                        channel_id: data.channel,
-                                        message_id,
-                                        new_content: Some(
-                                            FormattedText::default().with_text(new_content.clone()),
-                                        ),
-                                    }),
-                                    |_| async {
-                                        test(
-                                            "compare get message",
-                                            client.call(GetMessageRequest {
-                                                guild_id: data.guild,
-                                                channel_id: data.channel,
-                                                message_id,
-                                            }),
-                                            |response| async move {
-                                                check!(
-                                                    response.message.as_ref().unwrap().text(),
-                                                    Some(new_content.as_str())
-                                                );
-                                            },
-                                        )
-                                        .await;
-                                    },
-                                )
-                                .await;
+                    .await?;
+                    // other tests post in the channel concurrently, so ours may not be first
+                    let our_msg = response
+                        .messages
+                        .iter()
+                        .find(|m| m.message_id == sent.message_id)
+                        .and_then(|m| m.message.as_ref());
+                    check_some!(our_msg);
+                    if let Some(our_msg) = our_msg {
+                        check!(our_msg.text(), Some(msg.as_str()));
+                        check_within!(our_msg.created_at, current_time, 5 * 60);
+                    }
+                    TestResult::Ok(response)
+                }
+            },
+        )
+        .covers::<GetChannelMessagesRequest>();
+
+    let new_content = random_string();
+    suite
+        .test(
+            "edit message",
+            &["send message", "get channel messages", fixtures::GUILD],
+            &[Tag::Chat, Tag::Destructive],
+            {
+                let new_content = new_content.clone();
+                move |deps| {
+                    let new_content = new_content.clone();
+                    async move {
+                        let client = deps.get::<Client>("client connection");
+                        let guild = deps.get::<GuildFixture>(fixtures::GUILD);
+                        let sent = deps.get::<SendMessageResponse>("send message");
+                        call!(
+                            client,
+                            UpdateMessageTextRequest {
+                                guild_id: guild.guild_id,
+                                channel_id: guild.channel_id,
+                                message_id: sent.message_id,
+                                new_content: Some(FormattedText::default().with_text(new_content)),
                             }
-                        },
-                    )
-                    .await;
-
-                    test_no_hand(
-                        "instant view",
-                        client.call(InstantViewRequest::new(INSTANT_VIEW_URL.to_string())),
-                    )
-                    .await;
-
-                    test_no_hand(
-                        "can instant view",
-                        client.call(CanInstantViewRequest::new(INSTANT_VIEW_URL.to_string())),
+                        )
+                        .await
+                    }
+                }
+            },
+        )
+        .covers::<UpdateMessageTextRequest>();
+
+    suite
+        .test(
+            "compare get message",
+            &["edit message", fixtures::GUILD],
+            &[Tag::Chat],
+            move |deps| {
+                let new_content = new_content.clone();
+                async move {
+                    let client = deps.get::<Client>("client connection");
+                    let guild = deps.get::<GuildFixture>(fixtures::GUILD);
+                    let sent = deps.get::<SendMessageResponse>("send message");
+                    let response = call!(
+                        client,
+                        GetMessageRequest {
+                            guild_id: guild.guild_id,
+                            channel_id: guild.channel_id,
+                            message_id: sent.message_id,
+                        }
                     )
-                    .await;
+                    .await?;
+                    let message = response.message.as_ref().ok_or("response has no message")?;
+                    check!(message.text(), Some(new_content.as_str()));
+                    TestResult::Ok(response)
+                }
+            },
+        )
+        .covers::<GetMessageRequest>();
+
+    suite
+        .test(
+            "instant view",
+            &["client auth"],
+            &[Tag::Media, Tag::Slow],
+            |deps| async move {
+                let client = deps.get::<Client>("client connection");
+                call!(
+                    client,
+                    InstantViewRequest::new(config.instant_view_url.clone())
+                )
+                .await
+            },
+        )
+        .covers::<InstantViewRequest>();
+
+    suite
+        .test(
+            "can instant view",
+            &["client auth"],
+            &[Tag::Media, Tag::Slow],
+            |deps| async move {
+                let client = deps.get::<Client>("client connection");
+                call!(
+                    client,
+                    CanInstantViewRequest::new(config.instant_view_url.clone())
+                )
+                .await
+            },
+        )
+        .covers::<CanInstantViewRequest>();
+
+    suite
+        .test(
+            "fetch link metadata",
+            &["client auth"],
+            &[Tag::Media, Tag::Slow],
+            |deps| async move {
+                let client = deps.get::<Client>("client connection");
+                call!(
+                    client,
+                    FetchLinkMetadataRequest::new(config.instant_view_url.clone(),)
+                )
+                .await
+            },
+        )
+        .covers::<FetchLinkMetadataRequest>();
+
+    suite.test(
+        "upload media",
+        &["client auth"],
+        &[Tag::Media],
+        |deps| async move {
+            let client = deps.get::<Client>("client connection");
+            let response = rest::upload(
+                &client,
+                FILENAME.to_string(),
+                CONTENT_TYPE.to_string(),
+                FILE_DATA.as_bytes().to_vec(),
+            )
+            .await?;
+            TestResult::Ok(response.text().await?)
+        },
+    );
 
-                    test_no_hand(
-                        "fetch link metadata",
-                        client.call(FetchLinkMetadataRequest::new(INSTANT_VIEW_URL.to_string())),
-                    )
-                    .await;
-
-                    test(
-                        "upload media",
-                        rest::upload(
-                            &client,
-                            FILENAME.to_string(),
-                            CONTENT_TYPE.to_string(),
-                            FILE_DATA.as_bytes().to_vec(),
-                        ),
-                        |response| async {
-                            test_no_hand("upload response id", response.text()).await;
-                        },
-                    )
-                    .await;
-
-                    test(
-                        "download media",
-                        rest::download(&client, FileId::Id(data.file_id.to_string())),
-                        |response| async {
-                            let content_type = response
-                                .headers()
-                                .get("Content-Type")
-                                .map(|c| c.to_str().ok().map(|c| c.to_string()))
-                                .flatten();
-
-                            if let Some(content_type) = content_type {
-                                test(
-                                    "download response text",
-                                    response.text(),
-                                    |response| async move {
-                                        check!(response.as_str(), FILE_DATA);
-                                    },
-                                )
-                                .await;
-                                check!(content_type.as_str(), CONTENT_TYPE);
-                            }
-                        },
-                    )
-                    .await;
-
-                    test(
-                        "download external file",
-                        rest::download(&client, FileId::External(EXTERNAL_URL.parse().unwrap())),
-                        |response| async {
-                            if response.bytes().await.is_err() {
-                                tracing::error!("failed to download external file bytes");
-                            } else {
-                                tracing::info!("successfully downloaded external file bytes");
-                            }
-                        },
-                    )
-                    .await;
-
-                    test(
-                        "get guild channels",
-                        client.call(GetGuildChannelsRequest::new(data.guild)),
-                        |response| async move {
-                            check!(response.channels.len(), 1);
-                        },
-                    )
-                    .await;
-
-                    test(
-                        "create channel",
-                        client.call(CreateChannel::new(data.guild, "test".to_string())),
-                        |response| {
-                            let client = client.clone();
-                            async move {
-                                test(
-                                    "get channels compare new",
-                                    client.call(GetGuildChannelsRequest::new(data.guild)),
-                                    |response| async move {
-                                        check!(response.channels.len(), 2);
-                                    },
-                                )
-                                .await;
-                                test(
-                                    "delete channel",
-                                    client
-                                        .call(DeleteChannel::new(data.guild, response.channel_id)),
-                                    |_| async {
-                                        test(
-                                            "get channels compare delete",
-                                            client.call(GetGuildChannelsRequest::new(data.guild)),
-                                            |response| async move {
-                                                check!(response.channels.len(), 1);
-                                            },
-                                        )
-                                        .await;
-                                    },
-                                )
-                                .await;
-                            }
-                        },
-                    )
-                    .await;
+    suite.test(
+        "upload response id",
+        &["upload media"],
+        &[Tag::Media],
+        |deps| async move {
+            let id = deps.get::<String>("upload media");
+            check_ne!(id.as_str(), "");
+            TestResult::Ok(())
+        },
+    );
 
-                    test_no_hand(
-                        "get guild information",
-                        client.call(GetGuildRequest::new(data.guild)),
-                    )
-                    .await;
-
-                    let new_name = rand::thread_rng()
-                        .sample_iter(rand::distributions::Alphanumeric)
-                        .take(16)
-                        .map(|c| c as char)
-                        .collect::<String>();
-
-                    test(
-                        "update guild information",
-                        client.call(
-                            UpdateGuildInformation::new(data.guild)
-                                .with_new_guild_name(new_name.clone()),
-                        ),
-                        {
-                            let client = client.clone();
-                            move |_| async move {
-                                test(
-                                    "compare new info",
-                                    client.call(GetGuildRequest::new(data.guild)),
-                                    |response| async move {
-                                        check!(response.guild.as_ref().unwrap().name, new_name);
-                                    },
-                                )
-                                .await;
-                            }
-                        },
-                    )
-                    .await;
-
-                    test(
-                        "create guild",
-                        client.call(CreateGuild::new("test".to_string())),
-                        |response| {
-                            let client = client.clone();
-                            async move {
-                                test_no_hand(
-                                    "delete guild",
-                                    client.call(DeleteGuildRequest::new(response.guild_id)),
-                                )
-                                .await;
-                            }
-                        },
-                    )
-                    .await;
-
-                    test(
-                        "query has permission",
-                        client.call(
-                            QueryHasPermission::new(data.guild, "messages.send".to_string())
-                                .with_channel_id(data.channel),
-                        ),
-                        |response| async move {
-                            check!(response.ok, true);
-                        },
-                    )
-                    .await;
-
-                    test(
-                        "set profile offline",
-                        client.call(
-                            UpdateProfile::default()
-                                .with_new_status(UserStatus::OfflineUnspecified),
-                        ),
-                        |_| async {
-                            test(
-                                "compare profile status",
-                                client.call(GetProfileRequest::new(user_id)),
-                                |response| async move {
-                                    check!(
-                                        response.profile.as_ref().unwrap().user_status,
-                                        i32::from(UserStatus::OfflineUnspecified)
-                                    );
-                                },
-                            )
-                            .await;
-                        },
-                    )
-                    .await;
-
-                    test(
-                        "set profile bot",
-                        client.call(UpdateProfile::default().with_new_is_bot(true)),
-                        |_| async {
-                            test(
-                                "compare profile bot",
-                                client.call(GetProfileRequest::new(user_id)),
-                                |response| async move {
-                                    check!(response.profile.as_ref().unwrap().is_bot, true);
-                                },
-                            )
-                            .await;
-                        },
-                    )
-                    .await;
-                },
-            )
-            .await;
+    suite.test(
+        "download media",
+        &["client auth", fixtures::FILE],
+        &[Tag::Media],
+        |deps| async move {
+            let client = deps.get::<Client>("client connection");
+            let file = deps.get::<FileFixture>(fixtures::FILE);
+            let response = rest::download(&client, FileId::Id(file.file_id.clone())).await?;
+            let content_type = response
+                .headers()
+                .get("Content-Type")
+                .and_then(|c| c.to_str().ok().map(|c| c.to_string()));
+            check_matches!(content_type.as_deref(), None | Some(CONTENT_TYPE));
+            TestResult::Ok(response.text().await?)
         },
-    )
-    .await;
+    );
 
-    unsafe { TESTS_COMPLETE }
-}
+    suite.test(
+        "download response text",
+        &["download media"],
+        &[Tag::Media],
+        |deps| async move {
+            let text = deps.get::<String>("download media");
+            check!(text.as_str(), FILE_DATA);
+            TestResult::Ok(())
+        },
+    );
 
-async fn test<Fut, HandFut, Hand, Out, Err>(name: &'static str, res: Fut, hand: Hand)
-where
-    Err: std::error::Error,
-    Out: Debug,
-    Fut: Future<Output = Result<Out, Err>>,
-    HandFut: Future<Output = ()>,
-    Hand: FnOnce(Out) -> HandFut,
-{
-    info!("Testing {}...", name);
-    unsafe {
-        TESTS_TOTAL += 1;
-    }
-    let ins = Instant::now();
-    async {
-        match res.await {
-            Ok(val) => {
-                let time_passed = ins.elapsed();
-                unsafe {
-                    TOTAL_TIME += time_passed;
+    suite
+        .test(
+            "download external file",
+            &["client auth"],
+            &[Tag::Media, Tag::Slow],
+            |deps| async move {
+                let client = deps.get::<Client>("client connection");
+                let response =
+                    rest::download(&client, FileId::External(config.external_url.parse()?)).await?;
+                if response.bytes().await.is_err() {
+                    tracing::error!("failed to download external file bytes");
+                } else {
+                    tracing::info!("successfully downloaded external file bytes");
                 }
-                info!("successful in {} ns", time_passed.as_nanos());
-                info!("response: {:?}", val);
-                unsafe {
-                    TESTS_COMPLETE += 1;
+                TestResult::Ok(())
+            },
+        )
+        // the target proxies a third party server
+        .timeout(Duration::from_secs(60));
+
+    suite
+        .test(
+            "count guild channels",
+            // after the channels other tests provision, which would race the
+            // channel checks in the guild otherwise
+            &[
+                "client auth",
+                session::CHANNEL,
+                users::CHANNEL,
+                fixtures::GUILD,
+            ],
+            &[Tag::Chat],
+            move |deps| async move {
+                let client = deps.get::<Client>("client connection");
+                let guild = deps.get::<GuildFixture>(fixtures::GUILD);
+                let response = call!(client, GetGuildChannelsRequest::new(guild.guild_id)).await?;
+                // the provisioned channel, and whatever the server creates with a guild
+                check_ne!(response.channels.len(), 0);
+                ClientResult::Ok(response)
+            },
+        )
+        .covers::<GetGuildChannelsRequest>();
+
+    suite
+        .test(
+            "create channel",
+            &["client auth", "count guild channels", fixtures::GUILD],
+            &[Tag::Chat, Tag::Destructive],
+            move |deps| async move {
+                let client = deps.get::<Client>("client connection");
+                let guild = deps.get::<GuildFixture>(fixtures::GUILD);
+                call!(
+                    client,
+                    CreateChannel::new(guild.guild_id, "test".to_string())
+                )
+                .await
+            },
+        )
+        .covers::<CreateChannelRequest>();
+
+    suite
+        .test(
+            "get channels compare new",
+            &["create channel", fixtures::GUILD],
+            &[Tag::Chat],
+            move |deps| async move {
+                let client = deps.get::<Client>("client connection");
+                let guild = deps.get::<GuildFixture>(fixtures::GUILD);
+                let created = deps.get::<CreateChannelResponse>("create channel");
+                let response = call!(client, GetGuildChannelsRequest::new(guild.guild_id)).await?;
+                // other tests create channels in the guild concurrently, so
+                // only the created one is looked for rather than counted
+                let channel_ids = response
+                    .channels
+                    .iter()
+                    .map(|channel| channel.channel_id)
+                    .collect::<Vec<_>>();
+                check_contains!(channel_ids, &created.channel_id);
+                ClientResult::Ok(response)
+            },
+        )
+        .covers::<GetGuildChannelsRequest>();
+
+    suite
+        .test(
+            "delete channel",
+            &[
+                "create channel",
+                "get channels compare new",
+                fixtures::GUILD,
+            ],
+            &[Tag::Chat, Tag::Destructive],
+            move |deps| async move {
+                let client = deps.get::<Client>("client connection");
+                let guild = deps.get::<GuildFixture>(fixtures::GUILD);
+                let created = deps.get::<CreateChannelResponse>("create channel");
+                call!(
+                    client,
+                    DeleteChannel::new(guild.guild_id, created.channel_id)
+                )
+                .await
+            },
+        )
+        .covers::<DeleteChannelRequest>();
+
+    suite
+        .test(
+            "get channels compare delete",
+            &["delete channel", fixtures::GUILD],
+            &[Tag::Chat],
+            move |deps| async move {
+                let client = deps.get::<Client>("client connection");
+                let guild = deps.get::<GuildFixture>(fixtures::GUILD);
+                let created = deps.get::<CreateChannelResponse>("create channel");
+                let response = call!(client, GetGuildChannelsRequest::new(guild.guild_id)).await?;
+                check!(
+                    response
+                        .channels
+                        .iter()
+                        .any(|channel| channel.channel_id == created.channel_id),
+                    false
+                );
+                ClientResult::Ok(response)
+            },
+        )
+        .covers::<GetGuildChannelsRequest>();
+
+    suite
+        .test(
+            "get guild information",
+            &["client auth", fixtures::GUILD],
+            &[Tag::Chat],
+            move |deps| async move {
+                let client = deps.get::<Client>("client connection");
+                let guild = deps.get::<GuildFixture>(fixtures::GUILD);
+                call!(client, GetGuildRequest::new(guild.guild_id)).await
+            },
+        )
+        .covers::<GetGuildRequest>();
+
+    let new_name = random_string();
+    suite
+        .test(
+            "update guild information",
+            &["client auth", fixtures::GUILD],
+            &[Tag::Chat, Tag::Destructive],
+            {
+                let new_name = new_name.clone();
+                move |deps| {
+                    let new_name = new_name.clone();
+                    async move {
+                        let client = deps.get::<Client>("client connection");
+                        let guild = deps.get::<GuildFixture>(fixtures::GUILD);
+                        call!(
+                            client,
+                            UpdateGuildInformation::new(guild.guild_id)
+                                .with_new_guild_name(new_name),
+                        )
+                        .await
+                    }
                 }
-                hand(val).await
-            }
-            Err(err) => error!("error occured: {}", err),
-        }
-    }
-    .await
+            },
+        )
+        .covers::<UpdateGuildInformationRequest>();
+
+    suite
+        .test(
+            "compare new info",
+            &["update guild information", fixtures::GUILD],
+            &[Tag::Chat],
+            move |deps| {
+                let new_name = new_name.clone();
+                async move {
+                    let client = deps.get::<Client>("client connection");
+                    let guild = deps.get::<GuildFixture>(fixtures::GUILD);
+                    let response = call!(client, GetGuildRequest::new(guild.guild_id)).await?;
+                    let info = response.guild.as_ref().ok_or("response has no guild")?;
+                    check!(info.name, new_name);
+                    TestResult::Ok(response)
+                }
+            },
+        )
+        .covers::<GetGuildRequest>();
+
+    suite
+        .test(
+            "create guild",
+            &["client auth", "get guild list"],
+            &[Tag::Chat, Tag::Destructive],
+            |deps| async move {
+                let client = deps.get::<Client>("client connection");
+                call!(client, CreateGuild::new("test".to_string())).await
+            },
+        )
+        .covers::<CreateGuildRequest>();
+
+    suite
+        .test(
+            "delete guild",
+            &["create guild"],
+            &[Tag::Chat, Tag::Destructive],
+            |deps| async move {
+                let client = deps.get::<Client>("client connection");
+                let created = deps.get::<CreateGuildResponse>("create guild");
+                call!(client, DeleteGuildRequest::new(created.guild_id)).await
+            },
+        )
+        .covers::<DeleteGuildRequest>();
+
+    suite
+        .test(
+            "query has permission",
+            &["client auth", fixtures::GUILD],
+            &[Tag::Chat],
+            move |deps| async move {
+                let client = deps.get::<Client>("client connection");
+                let guild = deps.get::<GuildFixture>(fixtures::GUILD);
+                let response = call!(
+                    client,
+                    QueryHasPermission::new(guild.guild_id, "messages.send".to_string())
+                        .with_channel_id(guild.channel_id),
+                )
+                .await?;
+                check!(response.ok, true);
+                ClientResult::Ok(response)
+            },
+        )
+        .covers::<QueryHasPermissionRequest>();
+
+    suite
+        .test(
+            "set profile offline",
+            &["client auth", "profile update"],
+            &[Tag::Destructive],
+            |deps| async move {
+                let client = deps.get::<Client>("client connection");
+                call!(
+                    client,
+                    UpdateProfile::default().with_new_status(UserStatus::OfflineUnspecified)
+                )
+                .await
+            },
+        )
+        .covers::<UpdateProfileRequest>();
+
+    suite
+        .test(
+            "compare profile status",
+            &["set profile offline"],
+            &[],
+            |deps| async move {
+                let client = deps.get::<Client>("client connection");
+                let response = call!(client, GetProfileRequest::new(user_id(&client)?)).await?;
+                let profile = response.profile.as_ref().ok_or("response has no profile")?;
+                check!(
+                    profile.user_status,
+                    i32::from(UserStatus::OfflineUnspecified)
+                );
+                TestResult::Ok(response)
+            },
+        )
+        .covers::<GetProfileRequest>();
+
+    suite
+        .test(
+            "set profile bot",
+            &["client auth"],
+            &[Tag::Destructive],
+            |deps| async move {
+                let client = deps.get::<Client>("client connection");
+                call!(client, UpdateProfile::default().with_new_is_bot(true)).await
+            },
+        )
+        .covers::<UpdateProfileRequest>();
+
+    suite
+        .test(
+            "compare profile bot",
+            &["set profile bot"],
+            &[],
+            |deps| async move {
+                let client = deps.get::<Client>("client connection");
+                let response = call!(client, GetProfileRequest::new(user_id(&client)?)).await?;
+                let profile = response.profile.as_ref().ok_or("response has no profile")?;
+                check!(profile.is_bot, true);
+                TestResult::Ok(response)
+            },
+        )
+        .covers::<GetProfileRequest>();
+
+    suite
 }
 
-async fn test_no_hand<Fut, Out, Err>(name: &'static str, res: Fut)
-where
-    Err: std::error::Error,
-    Out: Debug,
-    Fut: Future<Output = Result<Out, Err>>,
-{
-    test(name, res, |_| async {}).await
+/// Id of the user `client` is logged in as.
+fn user_id(client: &Client) -> TestResult<u64> {
+    client
+        .auth_status()
+        .session()
+        .map(|session| session.user_id)
+        .ok_or_else(|| "client has no session".into())
 }
 
-#[macro_export]
-macro_rules! check {
-    ($res:expr, $res2:expr) => {
-        unsafe {
-            TESTS_TOTAL += 1;
-        }
-        if $res != $res2 {
-            error!("check unsuccessful: {:?} != {:?}", $res, $res2);
-        } else {
-            unsafe {
-                TESTS_COMPLETE += 1;
-            }
-        }
-    };
+fn random_string() -> String {
+    rand::thread_rng()
+        .sample_iter(rand::distributions::Alphanumeric)
+        .take(16)
+        .map(|c| c as char)
+        .collect()
 }
 
-use std::{
-    fmt::{self, Debug},
-    future::Future,
-    time::Duration,
-};
+use std::{fmt, time::Duration};
 use tracing::{Event, Subscriber};
 use tracing_subscriber::fmt::{
-    format::Writer as TracingWriter, FmtContext, FormatEvent, FormatFields,
+    format::Writer as TracingWriter, writer::BoxMakeWriter, FmtContext, FormatEvent, FormatFields,
+    FormattedFields,
 };
 use tracing_subscriber::registry::LookupSpan;
 
@@ -647,7 +1114,15 @@ where
 
             // Write spans and fields of each span
             ctx.visit_spans(|span| match span.name() {
-                "client connection" | "client auth" => Ok(()),
+                // these spans only carry their name as a field
+                "target" | "test" => match span.extensions().get::<FormattedFields<N>>() {
+                    Some(fields) => write!(
+                        &mut writer,
+                        "/{}",
+                        fields.fields.trim_start_matches("name=").replace(' ', "_")
+                    ),
+                    None => Ok(()),
+                },
                 _ => write!(&mut writer, "/{}", span.name().replace(' ', "_")),
             })?;
 
@@ -0,0 +1,350 @@
+use std::{fmt, str::FromStr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tag {
+    Auth,
+    Chat,
+    Media,
+    /// Changes server state other tests or users might observe.
+    Destructive,
+    /// Depends on third party services, or is otherwise slow.
+    Slow,
+}
+
+impl Tag {
+    pub const ALL: [Tag; 5] = [
+        Tag::Auth,
+        Tag::Chat,
+        Tag::Media,
+        Tag::Destructive,
+        Tag::Slow,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Tag::Auth => "auth",
+            Tag::Chat => "chat",
+            Tag::Media => "media",
+            Tag::Destructive => "destructive",
+            Tag::Slow => "slow",
+        }
+    }
+}
+
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Tag {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Tag::ALL
+            .iter()
+            .copied()
+            .find(|tag| tag.as_str() == s)
+            .ok_or_else(|| {
+                let all = Tag::ALL.iter().map(Tag::as_str).collect::<Vec<_>>();
+                format!("unknown tag {}, expected one of {}", s, all.join(", "))
+            })
+    }
+}
+
+/// A test of the suite and the tests it depends on.
+///
+/// The first dependency is the test it is nested under in reports.
+#[derive(Debug, Clone)]
+pub struct PlannedTest {
+    pub name: &'static str,
+    pub deps: Vec<&'static str>,
+    pub tags: &'static [Tag],
+    /// Runs after every other test, once the tests it depends on passed.
+    pub teardown: bool,
+    /// Paths of the hRPC endpoints the test calls.
+    pub endpoints: Vec<&'static str>,
+}
+
+impl PlannedTest {
+    pub fn parent(&self) -> Option<&'static str> {
+        self.deps.first().copied()
+    }
+}
+
+/// Which tests of the suite should run.
+#[derive(Debug, Default)]
+pub struct Selection {
+    pub filters: Vec<String>,
+    pub skips: Vec<String>,
+    pub tags: Vec<Tag>,
+    pub skip_tags: Vec<Tag>,
+}
+
+impl Selection {
+    /// Selects the tests matching the filters and tags together with the tests
+    /// nested under them, and everything those depend on, then removes skipped
+    /// tests and the tests depending on them.
+    ///
+    /// Teardowns are selected whenever everything they depend on is.
+    pub fn apply(&self, suite: &[PlannedTest]) -> Vec<&'static str> {
+        let matches = |test: &PlannedTest| {
+            (self.filters.is_empty() || self.filters.iter().any(|f| glob_match(f, test.name)))
+                && (self.tags.is_empty() || self.tags.iter().any(|t| test.tags.contains(t)))
+        };
+        let skipped = |test: &PlannedTest| {
+            self.skips.iter().any(|s| glob_match(s, test.name))
+                || self.skip_tags.iter().any(|t| test.tags.contains(t))
+        };
+
+        let matched = suite
+            .iter()
+            .filter(|test| matches(test) || parents(suite, test.name).any(matches))
+            .collect::<Vec<_>>();
+        let mut selected = suite
+            .iter()
+            .filter(|test| {
+                matched.iter().any(|m| {
+                    m.name == test.name || dependencies(suite, m.name).contains(&test.name)
+                })
+            })
+            .filter(|test| {
+                !skipped(test)
+                    && !dependencies(suite, test.name)
+                        .iter()
+                        .filter_map(|dep| find(suite, dep))
+                        .any(skipped)
+            })
+            .map(|test| test.name)
+            .collect::<Vec<_>>();
+        let teardowns = suite
+            .iter()
+            .filter(|test| {
+                test.teardown
+                    && !selected.contains(&test.name)
+                    && !skipped(test)
+                    && test.deps.iter().all(|dep| selected.contains(dep))
+            })
+            .map(|test| test.name)
+            .collect::<Vec<_>>();
+        selected.extend(teardowns);
+        selected
+    }
+}
+
+fn find<'a>(suite: &'a [PlannedTest], name: &str) -> Option<&'a PlannedTest> {
+    suite.iter().find(|test| test.name == name)
+}
+
+/// Iterates over the tests `name` is nested under, innermost first.
+pub fn parents<'a>(
+    suite: &'a [PlannedTest],
+    name: &str,
+) -> impl Iterator<Item = &'a PlannedTest> + 'a {
+    let parent_of = move |name: &str| {
+        find(suite, name)
+            .and_then(|t| t.parent())
+            .and_then(|p| find(suite, p))
+    };
+    std::iter::successors(parent_of(name), move |t| parent_of(t.name))
+}
+
+/// Every test `name` depends on, directly or not.
+pub fn dependencies(suite: &[PlannedTest], name: &str) -> Vec<&'static str> {
+    let mut deps = find(suite, name).map_or_else(Vec::new, |test| test.deps.clone());
+    let mut i = 0;
+    while let Some(dep) = deps.get(i).copied() {
+        for transitive in find(suite, dep).into_iter().flat_map(|t| t.deps.iter()) {
+            if !deps.contains(transitive) {
+                deps.push(*transitive);
+            }
+        }
+        i += 1;
+    }
+    deps
+}
+
+/// Matches `text` against a pattern where `*` matches any run of characters
+/// and `?` matches a single one.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    t = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test(name: &'static str, deps: &[&'static str], tags: &'static [Tag]) -> PlannedTest {
+        PlannedTest {
+            name,
+            deps: deps.to_vec(),
+            tags,
+            teardown: false,
+            endpoints: Vec::new(),
+        }
+    }
+
+    /// conn <- auth <- guild <- message, auth <- media, and a teardown of guild.
+    fn suite() -> Vec<PlannedTest> {
+        vec![
+            test("conn", &[], &[]),
+            test("auth", &["conn"], &[Tag::Auth]),
+            test("guild", &["auth"], &[Tag::Chat, Tag::Destructive]),
+            test("message", &["guild"], &[Tag::Chat]),
+            test("media", &["auth"], &[Tag::Media, Tag::Slow]),
+            PlannedTest {
+                teardown: true,
+                ..test("cleanup", &["guild"], &[Tag::Chat])
+            },
+        ]
+    }
+
+    fn select(selection: Selection) -> Vec<&'static str> {
+        selection.apply(&suite())
+    }
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn glob_match_edge_cases() {
+        let cases = [
+            ("", "", true),
+            ("", "a", false),
+            ("a", "ab", false),
+            ("*", "", true),
+            ("**", "x", true),
+            ("?", "", false),
+            ("*?", "", false),
+            ("?", "é", true),
+            ("a*", "a", true),
+            ("a*a", "a", false),
+            ("a*b", "ab", true),
+            ("a*b", "abc", false),
+            ("a*b*c", "axxbyyc", true),
+            ("*aab", "aaab", true),
+            ("Auth", "auth", false),
+            ("client *", "client auth", true),
+            ("*auth*", "client auth step", true),
+        ];
+        for (pattern, text, expected) in cases {
+            assert_eq!(
+                glob_match(pattern, text),
+                expected,
+                "{:?} against {:?}",
+                pattern,
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn everything_by_default() {
+        assert_eq!(
+            select(Selection::default()),
+            ["conn", "auth", "guild", "message", "media", "cleanup"]
+        );
+    }
+
+    #[test]
+    fn filter_adds_dependencies_and_teardowns() {
+        let selection = Selection {
+            filters: strings(&["mess*"]),
+            ..Default::default()
+        };
+        assert_eq!(
+            select(selection),
+            ["conn", "auth", "guild", "message", "cleanup"]
+        );
+    }
+
+    #[test]
+    fn filter_adds_nested_tests() {
+        let selection = Selection {
+            filters: strings(&["auth"]),
+            ..Default::default()
+        };
+        assert_eq!(
+            select(selection),
+            ["conn", "auth", "guild", "message", "media", "cleanup"]
+        );
+    }
+
+    #[test]
+    fn skip_removes_dependents_and_their_teardowns() {
+        let selection = Selection {
+            filters: strings(&["*"]),
+            skips: strings(&["guild"]),
+            ..Default::default()
+        };
+        assert_eq!(select(selection), ["conn", "auth", "media"]);
+    }
+
+    #[test]
+    fn skipped_dependency_removes_everything_above() {
+        let selection = Selection {
+            filters: strings(&["message"]),
+            skips: strings(&["co?n"]),
+            ..Default::default()
+        };
+        assert!(select(selection).is_empty());
+    }
+
+    #[test]
+    fn tags_add_dependencies() {
+        let selection = Selection {
+            tags: vec![Tag::Chat],
+            skip_tags: vec![Tag::Slow],
+            ..Default::default()
+        };
+        assert_eq!(
+            select(selection),
+            ["conn", "auth", "guild", "message", "cleanup"]
+        );
+    }
+
+    #[test]
+    fn skip_tags_win_over_tags() {
+        let selection = Selection {
+            tags: vec![Tag::Media],
+            skip_tags: vec![Tag::Slow],
+            ..Default::default()
+        };
+        assert_eq!(select(selection), ["conn", "auth"]);
+    }
+
+    #[test]
+    fn skip_tags_apply_to_dependencies() {
+        let selection = Selection {
+            filters: strings(&["message"]),
+            skip_tags: vec![Tag::Destructive],
+            ..Default::default()
+        };
+        assert_eq!(select(selection), ["conn", "auth"]);
+    }
+}
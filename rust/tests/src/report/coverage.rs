@@ -0,0 +1,184 @@
+use std::{
+    any::type_name,
+    io::{self, Write},
+};
+
+use harmony_rust_sdk::api::{auth, batch, chat, emote, mediaproxy, profile, Endpoint};
+
+use crate::{plan::PlannedTest, runner::CallRecord};
+
+macro_rules! endpoints {
+    ($($endpoint:ty),* $(,)?) => {
+        &[$(<$endpoint as Endpoint>::ENDPOINT_PATH),*]
+    };
+}
+
+/// Paths of the client facing endpoints of the SDK.
+pub const ENDPOINTS: &[&str] = endpoints![
+    auth::BeginAuthRequest,
+    auth::NextStepRequest,
+    auth::StepBackRequest,
+    auth::StreamStepsRequest,
+    auth::FederateRequest,
+    auth::LoginFederatedRequest,
+    auth::KeyRequest,
+    auth::CheckLoggedInRequest,
+    batch::BatchRequest,
+    batch::BatchSameRequest,
+    chat::CreateGuildRequest,
+    chat::CreateInviteRequest,
+    chat::CreateChannelRequest,
+    chat::GetGuildListRequest,
+    chat::GetGuildRequest,
+    chat::GetGuildInvitesRequest,
+    chat::GetGuildMembersRequest,
+    chat::GetGuildChannelsRequest,
+    chat::GetChannelMessagesRequest,
+    chat::GetMessageRequest,
+    chat::UpdateGuildInformationRequest,
+    chat::UpdateChannelInformationRequest,
+    chat::UpdateChannelOrderRequest,
+    chat::UpdateAllChannelOrderRequest,
+    chat::UpdateMessageTextRequest,
+    chat::DeleteGuildRequest,
+    chat::DeleteInviteRequest,
+    chat::DeleteChannelRequest,
+    chat::DeleteMessageRequest,
+    chat::JoinGuildRequest,
+    chat::LeaveGuildRequest,
+    chat::TriggerActionRequest,
+    chat::SendMessageRequest,
+    chat::QueryHasPermissionRequest,
+    chat::SetPermissionsRequest,
+    chat::GetPermissionsRequest,
+    chat::MoveRoleRequest,
+    chat::GetGuildRolesRequest,
+    chat::AddGuildRoleRequest,
+    chat::ModifyGuildRoleRequest,
+    chat::DeleteGuildRoleRequest,
+    chat::ManageUserRolesRequest,
+    chat::GetUserRolesRequest,
+    chat::StreamEventsRequest,
+    chat::TypingRequest,
+    chat::PreviewGuildRequest,
+    chat::GetBannedUsersRequest,
+    chat::BanUserRequest,
+    chat::KickUserRequest,
+    chat::UnbanUserRequest,
+    chat::GetPinnedMessagesRequest,
+    chat::PinMessageRequest,
+    chat::UnpinMessageRequest,
+    chat::AddReactionRequest,
+    chat::RemoveReactionRequest,
+    chat::GrantOwnershipRequest,
+    chat::GiveUpOwnershipRequest,
+    emote::CreateEmotePackRequest,
+    emote::GetEmotePacksRequest,
+    emote::GetEmotePackEmotesRequest,
+    emote::AddEmoteToPackRequest,
+    emote::DeleteEmoteFromPackRequest,
+    emote::DeleteEmotePackRequest,
+    emote::DequipEmotePackRequest,
+    emote::EquipEmotePackRequest,
+    mediaproxy::FetchLinkMetadataRequest,
+    mediaproxy::InstantViewRequest,
+    mediaproxy::CanInstantViewRequest,
+    profile::GetProfileRequest,
+    profile::UpdateProfileRequest,
+    profile::GetAppDataRequest,
+    profile::SetAppDataRequest,
+];
+
+struct Row {
+    endpoint: &'static str,
+    called: usize,
+    succeeded: usize,
+    /// Selected tests declaring they call the endpoint.
+    tests: String,
+}
+
+impl Row {
+    fn status(&self) -> String {
+        match (self.called, self.succeeded) {
+            (0, _) => "never tested".to_string(),
+            (called, 0) => format!("called {}, none succeeded", called),
+            (called, succeeded) => format!("called {}, {} succeeded", called, succeeded),
+        }
+    }
+}
+
+/// The endpoint answering with a `T`, since the SDK names responses after
+/// their endpoint.
+pub fn endpoint_of<T>() -> Option<&'static str> {
+    let name = type_name::<T>().rsplit("::").next()?;
+    let method = name.strip_suffix("Response")?;
+    ENDPOINTS
+        .iter()
+        .copied()
+        .find(|path| path.rsplit('/').next() == Some(method))
+}
+
+/// Writes a table with a row per endpoint, whether the run called it and
+/// any call succeeded, and the selected tests declaring they call it.
+///
+/// Declarations come from [`PlannedTest::endpoints`], but only recorded calls
+/// count: a test restoring a cached session declares the auth endpoints
+/// without calling them.
+pub fn write(calls: &[CallRecord], plan: &[PlannedTest], mut out: impl Write) -> io::Result<()> {
+    let mut endpoints = ENDPOINTS.to_vec();
+    let extra = calls
+        .iter()
+        .map(|call| call.endpoint)
+        .chain(plan.iter().flat_map(|test| test.endpoints.iter().copied()));
+    for endpoint in extra {
+        if !endpoints.contains(&endpoint) {
+            endpoints.push(endpoint);
+        }
+    }
+
+    let rows = endpoints
+        .iter()
+        .map(|&endpoint| {
+            let made = calls.iter().filter(|call| call.endpoint == endpoint);
+            let tests = plan
+                .iter()
+                .filter(|test| test.endpoints.contains(&endpoint))
+                .map(|test| test.name)
+                .collect::<Vec<_>>();
+            Row {
+                endpoint,
+                called: made.clone().count(),
+                succeeded: made.filter(|call| call.ok).count(),
+                tests: tests.join(", "),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    writeln!(
+        out,
+        "coverage: {} of {} endpoints called, {} succeeded",
+        rows.iter().filter(|row| row.called > 0).count(),
+        rows.len(),
+        rows.iter().filter(|row| row.succeeded > 0).count(),
+    )?;
+    let statuses = rows.iter().map(Row::status).collect::<Vec<_>>();
+    let width = rows.iter().map(|row| row.endpoint.len()).max().unwrap_or(0);
+    let status_width = statuses.iter().map(String::len).max().unwrap_or(0);
+    for (row, status) in rows.iter().zip(&statuses) {
+        let tests = if row.tests.is_empty() {
+            "-"
+        } else {
+            row.tests.as_str()
+        };
+        writeln!(
+            out,
+            "{:width$} | {:status_width$} | {}",
+            row.endpoint,
+            status,
+            tests,
+            width = width,
+            status_width = status_width
+        )?;
+    }
+    Ok(())
+}
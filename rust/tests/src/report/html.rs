@@ -0,0 +1,167 @@
+use std::{
+    io::{self, Write},
+    time::Duration,
+};
+
+use crate::{
+    report::escape,
+    runner::{Outcome, RecordKind, TestRecord},
+};
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; }
+details { margin-left: 1.5em; border-left: 3px solid #ccc; padding-left: 0.5em; }
+summary { cursor: pointer; padding: 0.1em 0; }
+pre { background: #f4f4f4; padding: 0.5em; overflow-x: auto; white-space: pre-wrap; }
+.passed { border-color: #2a2; }
+.failed, .timed-out { border-color: #d22; }
+.skipped { border-color: #aaa; }
+.passed > summary .outcome { color: #2a2; }
+.failed > summary .outcome, .timed-out > summary .outcome { color: #d22; }
+.skipped > summary .outcome { color: #888; }
+.duration, .kind { color: #666; }
+pre.error { background: #fdecec; }
+";
+
+/// Records nested under the same span path.
+struct Node<'a> {
+    name: &'a str,
+    records: Vec<&'a TestRecord>,
+    children: Vec<Node<'a>>,
+}
+
+impl<'a> Node<'a> {
+    fn new(name: &'a str) -> Self {
+        Self {
+            name,
+            records: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    fn insert(&mut self, path: &[&'a str], record: &'a TestRecord) {
+        match path.split_first() {
+            Some((first, rest)) => {
+                let index = match self.children.iter().position(|c| c.name == *first) {
+                    Some(index) => index,
+                    None => {
+                        self.children.push(Node::new(first));
+                        self.children.len() - 1
+                    }
+                };
+                self.children[index].insert(rest, record);
+            }
+            None => self.records.push(record),
+        }
+    }
+
+    /// Worst outcome of this node and everything nested under it.
+    fn outcome(&self) -> Outcome {
+        let outcomes = self
+            .records
+            .iter()
+            .map(|r| r.outcome)
+            .chain(self.children.iter().map(Node::outcome))
+            .collect::<Vec<_>>();
+        [Outcome::TimedOut, Outcome::Failed, Outcome::Skipped]
+            .iter()
+            .copied()
+            .find(|outcome| outcomes.contains(outcome))
+            .unwrap_or(Outcome::Passed)
+    }
+
+    fn duration(&self) -> Duration {
+        if self.records.is_empty() {
+            self.children.iter().map(Node::duration).sum()
+        } else {
+            self.records.iter().map(|r| r.duration).sum()
+        }
+    }
+}
+
+/// Writes `records` as a self-contained HTML page, with a collapsible tree of
+/// the nested tests. Failures are expanded.
+pub fn write(records: &[TestRecord], mut out: impl Write) -> io::Result<()> {
+    let mut root = Node::new("");
+    for record in records {
+        let path = record
+            .path
+            .iter()
+            .map(String::as_str)
+            .chain(Some(record.name.as_str()))
+            .collect::<Vec<_>>();
+        root.insert(&path, record);
+    }
+
+    let tests = records.iter().filter(|r| r.kind == RecordKind::Test);
+    let count = |outcome: Outcome| tests.clone().filter(|r| r.outcome == outcome).count();
+
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, r#"<html lang="en">"#)?;
+    writeln!(out, r#"<head><meta charset="utf-8">"#)?;
+    writeln!(out, "<title>integration-testing report</title>")?;
+    writeln!(out, "<style>{}</style>", STYLE)?;
+    writeln!(out, "</head>")?;
+    writeln!(out, "<body>")?;
+    writeln!(out, "<h1>integration-testing report</h1>")?;
+    writeln!(
+        out,
+        "<p>{} tests: {} passed, {} failed, {} timed out, {} skipped</p>",
+        tests.clone().count(),
+        count(Outcome::Passed),
+        count(Outcome::Failed),
+        count(Outcome::TimedOut),
+        count(Outcome::Skipped),
+    )?;
+    for node in &root.children {
+        write_node(node, &mut out)?;
+    }
+    writeln!(out, "</body>")?;
+    writeln!(out, "</html>")
+}
+
+fn write_node(node: &Node, out: &mut impl Write) -> io::Result<()> {
+    let outcome = node.outcome();
+    let class = outcome.as_str().replace(' ', "-");
+    let open = if outcome.is_failure() { " open" } else { "" };
+    writeln!(out, r#"<details class="{}"{}>"#, class, open)?;
+    write!(
+        out,
+        r#"<summary>{} <span class="outcome">{}</span> <span class="duration">{}</span>"#,
+        escape(node.name),
+        outcome.as_str(),
+        millis(node.duration()),
+    )?;
+    if let Some(record) = node.records.first() {
+        if record.kind == RecordKind::Check {
+            write!(out, r#" <span class="kind">check</span>"#)?;
+        }
+    }
+    writeln!(out, "</summary>")?;
+
+    for (run, record) in node.records.iter().enumerate() {
+        if node.records.len() > 1 {
+            writeln!(
+                out,
+                "<p>run {}: {} in {}</p>",
+                run + 1,
+                record.outcome.as_str(),
+                millis(record.duration)
+            )?;
+        }
+        if let Some(err) = record.error.as_deref() {
+            writeln!(out, r#"<pre class="error">{}</pre>"#, escape(err))?;
+        }
+        if let Some(response) = record.response.as_deref() {
+            writeln!(out, "<pre>{}</pre>", escape(response))?;
+        }
+    }
+    for child in &node.children {
+        write_node(child, out)?;
+    }
+    writeln!(out, "</details>")
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{:.3} ms", duration.as_secs_f64() * 1000.0)
+}
@@ -0,0 +1,69 @@
+use std::{io::Write, sync::Mutex};
+
+use serde_json::{json, Value};
+use tracing::error;
+
+use crate::runner::{Listener, RecordKind, Summary, TestRecord};
+
+/// Streams one JSON object per line for every test start, finish and check.
+pub struct JsonStream {
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+impl JsonStream {
+    pub fn new(out: Box<dyn Write + Send>) -> Self {
+        Self {
+            out: Mutex::new(out),
+        }
+    }
+
+    fn emit(&self, event: Value) {
+        let mut out = self.out.lock().expect("json stream poisoned");
+        let written = serde_json::to_writer(&mut *out, &event)
+            .map_err(Into::into)
+            .and_then(|_| writeln!(out))
+            .and_then(|_| out.flush());
+        if let Err(err) = written {
+            error!("failed to write json event: {}", err);
+        }
+    }
+}
+
+impl Listener for JsonStream {
+    fn started(&self, path: &[String], name: &str) {
+        self.emit(json!({
+            "event": "start",
+            "path": path,
+            "name": name,
+        }));
+    }
+
+    fn finished(&self, record: &TestRecord) {
+        let event = match record.kind {
+            RecordKind::Test => "finish",
+            RecordKind::Check => "check",
+        };
+        self.emit(json!({
+            "event": event,
+            "path": record.path,
+            "name": record.name,
+            "outcome": record.outcome.as_str(),
+            "duration_ns": record.duration.as_nanos() as u64,
+            "error": record.error,
+            "response": record.response,
+        }));
+    }
+
+    fn completed(&self, summary: &Summary) {
+        self.emit(json!({
+            "event": "summary",
+            "total": summary.total,
+            "passed": summary.passed,
+            "failed": summary.failed,
+            "skipped": summary.skipped,
+            "timed_out": summary.timed_out,
+            "not_run": summary.not_run,
+            "duration_ns": summary.total_time.as_nanos() as u64,
+        }));
+    }
+}
@@ -0,0 +1,100 @@
+use std::{
+    io::{self, Write},
+    time::Duration,
+};
+
+use crate::{
+    report::escape,
+    runner::{Outcome, RecordKind, TestRecord},
+};
+
+/// Writes `records` as a JUnit XML report.
+///
+/// Every outermost path segment becomes a `testsuite`, and the whole
+/// span path is used as the `classname` of each `testcase`.
+pub fn write(records: &[TestRecord], mut out: impl Write) -> io::Result<()> {
+    let mut suites: Vec<(&str, Vec<&TestRecord>)> = Vec::new();
+    for record in records {
+        let suite = record.path.first().map_or("tests", String::as_str);
+        match suites.iter_mut().find(|(name, _)| *name == suite) {
+            Some((_, cases)) => cases.push(record),
+            None => suites.push((suite, vec![record])),
+        }
+    }
+
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        out,
+        r#"<testsuites name="integration-testing" tests="{}" failures="{}" skipped="{}" time="{}">"#,
+        records.len(),
+        failures(records.iter()),
+        count(records.iter(), Outcome::Skipped),
+        seconds(records.iter().map(|r| r.duration).sum()),
+    )?;
+    for (name, cases) in suites {
+        writeln!(
+            out,
+            r#"  <testsuite name="{}" tests="{}" failures="{}" skipped="{}" time="{}">"#,
+            escape(name),
+            cases.len(),
+            failures(cases.iter().copied()),
+            count(cases.iter().copied(), Outcome::Skipped),
+            seconds(cases.iter().map(|r| r.duration).sum()),
+        )?;
+        for case in cases {
+            write_case(case, &mut out)?;
+        }
+        writeln!(out, "  </testsuite>")?;
+    }
+    writeln!(out, "</testsuites>")
+}
+
+fn write_case(record: &TestRecord, out: &mut impl Write) -> io::Result<()> {
+    write!(
+        out,
+        r#"    <testcase classname="{}" name="{}" time="{}""#,
+        escape(&record.path.join(".")),
+        escape(&record.name),
+        seconds(record.duration),
+    )?;
+    if record.outcome == Outcome::Passed && record.response.is_none() {
+        return writeln!(out, "/>");
+    }
+    writeln!(out, ">")?;
+    if record.outcome == Outcome::Skipped {
+        writeln!(
+            out,
+            r#"      <skipped message="{}"/>"#,
+            escape(record.error.as_deref().unwrap_or_default()),
+        )?;
+    } else if let Some(err) = record.error.as_deref() {
+        let (kind, message) = match (record.outcome, record.kind) {
+            (Outcome::TimedOut, _) => ("timeout", err.to_string()),
+            (_, RecordKind::Test) => ("error", format!("error occured: {}", err)),
+            (_, RecordKind::Check) => ("check", format!("check unsuccessful: {}", err)),
+        };
+        writeln!(
+            out,
+            r#"      <failure type="{}" message="{}">{}</failure>"#,
+            kind,
+            escape(&message),
+            escape(&message),
+        )?;
+    }
+    if let Some(response) = record.response.as_deref() {
+        writeln!(out, "      <system-out>{}</system-out>", escape(response))?;
+    }
+    writeln!(out, "    </testcase>")
+}
+
+fn count<'a>(records: impl Iterator<Item = &'a TestRecord>, outcome: Outcome) -> usize {
+    records.filter(|r| r.outcome == outcome).count()
+}
+
+fn failures<'a>(records: impl Iterator<Item = &'a TestRecord>) -> usize {
+    records.filter(|r| r.outcome.is_failure()).count()
+}
+
+fn seconds(duration: Duration) -> String {
+    format!("{:.3}", duration.as_secs_f64())
+}
@@ -0,0 +1,74 @@
+use std::{
+    io::{self, Write},
+    time::Duration,
+};
+
+use crate::runner::{Outcome, RecordKind, TestRecord};
+
+/// Writes latency percentiles of every test on every target, out of the runs
+/// where the test passed.
+pub fn write(records: &[TestRecord], mut out: impl Write) -> io::Result<()> {
+    let mut tests: Vec<(&str, &str, Vec<Duration>)> = Vec::new();
+    for record in records
+        .iter()
+        .filter(|r| r.kind == RecordKind::Test && r.outcome == Outcome::Passed)
+    {
+        let target = record.path.first().map_or("", String::as_str);
+        match tests
+            .iter_mut()
+            .find(|(t, name, _)| *t == target && *name == record.name)
+        {
+            Some((_, _, durations)) => durations.push(record.duration),
+            None => tests.push((target, &record.name, vec![record.duration])),
+        }
+    }
+
+    let name_width = tests
+        .iter()
+        .map(|(target, name, _)| target.len() + name.len() + 3)
+        .max()
+        .unwrap_or(0)
+        .max(4);
+    writeln!(
+        out,
+        "{:width$} | {:>4} | {:>9} | {:>9} | {:>9} | {:>9} | {:>9}",
+        "test",
+        "runs",
+        "min ms",
+        "p50 ms",
+        "p95 ms",
+        "p99 ms",
+        "max ms",
+        width = name_width
+    )?;
+    writeln!(out, "{}", "-".repeat(name_width + 7 + 12 * 5))?;
+    for (target, name, mut durations) in tests {
+        durations.sort_unstable();
+        write!(
+            out,
+            "{:width$} | {:>4}",
+            format!("{} / {}", target, name),
+            durations.len(),
+            width = name_width
+        )?;
+        for percentile in [0.0, 50.0, 95.0, 99.0, 100.0] {
+            write!(
+                out,
+                " | {:>9.3}",
+                millis(nearest_rank(&durations, percentile))
+            )?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// Value under which `percentile` percent of the sorted `durations` are.
+fn nearest_rank(durations: &[Duration], percentile: f64) -> Duration {
+    let rank = (percentile / 100.0 * durations.len() as f64).ceil() as usize;
+    durations[rank.clamp(1, durations.len()) - 1]
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
@@ -0,0 +1,86 @@
+use std::io::{self, Write};
+
+use crate::runner::{Outcome, RecordKind, TestRecord};
+
+/// Writes a table with a row per test and a column per target, showing the
+/// outcome and latency of the test on each target.
+///
+/// A test that passed but had failing checks is marked as `CHECK`.
+pub fn write(records: &[TestRecord], targets: &[&str], mut out: impl Write) -> io::Result<()> {
+    let mut tests: Vec<&str> = Vec::new();
+    for record in records.iter().filter(|r| r.kind == RecordKind::Test) {
+        if !tests.contains(&record.name.as_str()) {
+            tests.push(&record.name);
+        }
+    }
+
+    let cells = tests
+        .iter()
+        .map(|test| {
+            targets
+                .iter()
+                .map(|target| cell(records, target, test))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let name_width = tests.iter().map(|t| t.len()).max().unwrap_or(0).max(4);
+    let widths = targets
+        .iter()
+        .enumerate()
+        .map(|(i, target)| {
+            cells
+                .iter()
+                .map(|row| row[i].len())
+                .chain(std::iter::once(target.len()))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<_>>();
+
+    write!(out, "{:width$}", "test", width = name_width)?;
+    for (target, width) in targets.iter().zip(&widths) {
+        write!(out, " | {:width$}", target, width = width)?;
+    }
+    writeln!(out)?;
+    write!(out, "{}", "-".repeat(name_width))?;
+    for width in &widths {
+        write!(out, "-+-{}", "-".repeat(*width))?;
+    }
+    writeln!(out)?;
+    for (test, row) in tests.iter().zip(&cells) {
+        write!(out, "{:width$}", test, width = name_width)?;
+        for (cell, width) in row.iter().zip(&widths) {
+            write!(out, " | {:width$}", cell, width = width)?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+fn cell(records: &[TestRecord], target: &str, test: &str) -> String {
+    let in_target = |r: &&TestRecord| r.path.first().map(String::as_str) == Some(target);
+    let record = match records
+        .iter()
+        .filter(in_target)
+        .find(|r| r.kind == RecordKind::Test && r.name == test)
+    {
+        Some(record) => record,
+        None => return "-".to_string(),
+    };
+    let failed_checks = records.iter().filter(in_target).any(|r| {
+        r.kind == RecordKind::Check
+            && r.outcome == Outcome::Failed
+            && r.path.last().map(String::as_str) == Some(test)
+    });
+    match record.outcome {
+        Outcome::Passed => format!(
+            "{} {:.1} ms",
+            if failed_checks { "CHECK" } else { "ok" },
+            record.duration.as_secs_f64() * 1000.0
+        ),
+        Outcome::Failed => "FAIL".to_string(),
+        Outcome::Skipped => "skip".to_string(),
+        Outcome::TimedOut => "TIMEOUT".to_string(),
+    }
+}
@@ -0,0 +1,40 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+pub mod coverage;
+pub mod html;
+pub mod json;
+pub mod junit;
+pub mod latency;
+pub mod matrix;
+pub mod tap;
+
+/// Opens `path` for writing a report, `-` meaning stdout.
+pub fn open(path: &Path) -> io::Result<Box<dyn Write + Send>> {
+    if path == Path::new("-") {
+        Ok(Box::new(io::stdout()))
+    } else {
+        File::create(path).map(|file| Box::new(BufWriter::new(file)) as Box<dyn Write + Send>)
+    }
+}
+
+/// Escapes `text` for HTML and XML reports alike.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            // control characters aren't allowed in XML 1.0 at all
+            c if c.is_control() && !matches!(c, '\n' | '\r' | '\t') => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,72 @@
+use std::{io::Write, sync::Mutex};
+
+use tracing::error;
+
+use crate::runner::{Listener, Outcome, Summary, TestRecord};
+
+/// Streams results in the Test Anything Protocol, with the plan written last.
+pub struct TapStream {
+    state: Mutex<(usize, Box<dyn Write + Send>)>,
+}
+
+impl TapStream {
+    pub fn new(mut out: Box<dyn Write + Send>) -> Self {
+        if let Err(err) = writeln!(out, "TAP version 13") {
+            error!("failed to write tap header: {}", err);
+        }
+        Self {
+            state: Mutex::new((0, out)),
+        }
+    }
+
+    fn write(&self, f: impl FnOnce(&mut usize, &mut dyn Write) -> std::io::Result<()>) {
+        let mut state = self.state.lock().expect("tap stream poisoned");
+        let (count, out) = &mut *state;
+        if let Err(err) = f(count, &mut *out).and_then(|_| out.flush()) {
+            error!("failed to write tap line: {}", err);
+        }
+    }
+}
+
+impl Listener for TapStream {
+    fn started(&self, _path: &[String], _name: &str) {}
+
+    fn finished(&self, record: &TestRecord) {
+        self.write(|count, out| {
+            *count += 1;
+            let name = record.full_name().replace('#', "\\#");
+            match record.outcome {
+                Outcome::Passed => writeln!(out, "ok {} - {}", count, name)?,
+                Outcome::Failed | Outcome::TimedOut => {
+                    writeln!(out, "not ok {} - {}", count, name)?
+                }
+                Outcome::Skipped => {
+                    let reason = record.error.as_deref().unwrap_or_default();
+                    return writeln!(out, "ok {} - {} # SKIP {}", count, name, reason);
+                }
+            }
+            writeln!(out, "  ---")?;
+            writeln!(out, "  kind: {}", record.kind.as_str())?;
+            writeln!(
+                out,
+                "  duration_ms: {}",
+                record.duration.as_secs_f64() * 1000.0
+            )?;
+            if let Some(err) = record.error.as_deref() {
+                writeln!(out, "  message: {:?}", err)?;
+            }
+            writeln!(out, "  ...")
+        });
+    }
+
+    fn completed(&self, summary: &Summary) {
+        self.write(|count, out| {
+            writeln!(out, "1..{}", count)?;
+            writeln!(
+                out,
+                "# passed {}, failed {}, timed out {}, skipped {}, never ran {}",
+                summary.passed, summary.failed, summary.timed_out, summary.skipped, summary.not_run
+            )
+        });
+    }
+}
@@ -0,0 +1,416 @@
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{plan::PlannedTest, report::coverage};
+
+tokio::task_local! {
+    static CONTEXT: TestContext;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    Failed,
+    /// Not ran because a test it depends on failed.
+    Skipped,
+    /// Didn't finish within its timeout.
+    TimedOut,
+}
+
+impl Outcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Outcome::Passed => "passed",
+            Outcome::Failed => "failed",
+            Outcome::Skipped => "skipped",
+            Outcome::TimedOut => "timed out",
+        }
+    }
+
+    pub fn is_failure(&self) -> bool {
+        matches!(self, Outcome::Failed | Outcome::TimedOut)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordKind {
+    Test,
+    Check,
+}
+
+impl RecordKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RecordKind::Test => "test",
+            RecordKind::Check => "check",
+        }
+    }
+}
+
+/// Result of a single `test` or `check!` invocation.
+#[derive(Debug, Clone)]
+pub struct TestRecord {
+    pub kind: RecordKind,
+    pub name: String,
+    /// Names of the enclosing tests, outermost first.
+    pub path: Vec<String>,
+    pub duration: Duration,
+    pub outcome: Outcome,
+    /// Why the test failed or was skipped.
+    pub error: Option<String>,
+    pub response: Option<String>,
+}
+
+impl TestRecord {
+    pub fn full_name(&self) -> String {
+        let mut full = self.path.join(" / ");
+        if !full.is_empty() {
+            full.push_str(" / ");
+        }
+        full.push_str(&self.name);
+        full
+    }
+}
+
+/// A call a test made to an endpoint of the target.
+#[derive(Debug, Clone)]
+pub struct CallRecord {
+    pub endpoint: &'static str,
+    /// Names of the test making the call and the tests enclosing it,
+    /// outermost first.
+    pub path: Vec<String>,
+    /// Whether the target answered without an error.
+    pub ok: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Summary {
+    pub total: usize,
+    pub passed: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    pub timed_out: usize,
+    /// Planned tests that have no record.
+    pub not_run: usize,
+    pub total_time: Duration,
+}
+
+/// Receives test events live, as the run progresses.
+pub trait Listener: Send + Sync {
+    fn started(&self, path: &[String], name: &str);
+    fn finished(&self, record: &TestRecord);
+    fn completed(&self, _summary: &Summary) {}
+}
+
+/// Collects every test and check record of a run.
+///
+/// Cloning is cheap, all clones share the same registry.
+#[derive(Clone, Default)]
+pub struct TestRunner {
+    records: Arc<Mutex<Vec<TestRecord>>>,
+    calls: Arc<Mutex<Vec<CallRecord>>>,
+    listeners: Arc<Vec<Box<dyn Listener>>>,
+    plan: Arc<Vec<PlannedTest>>,
+    roots: Arc<Mutex<Vec<String>>>,
+}
+
+impl TestRunner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_listener(mut self, listener: impl Listener + 'static) -> Self {
+        Arc::get_mut(&mut self.listeners)
+            .expect("listeners must be added before the runner is used")
+            .push(Box::new(listener));
+        self
+    }
+
+    /// Runs `fut` with this runner as the current one, with `root` as the outermost path segment.
+    pub async fn scope<Fut: Future>(&self, root: &str, fut: Fut) -> Fut::Output {
+        {
+            let mut roots = self.roots.lock().expect("test registry poisoned");
+            if !roots.iter().any(|r| r == root) {
+                roots.push(root.to_string());
+            }
+        }
+        let context = TestContext {
+            runner: self.clone(),
+            path: vec![root.to_string()],
+        };
+        CONTEXT.scope(context, fut).await
+    }
+
+    /// Sets every test that is expected to run, other tests are left out.
+    pub fn with_plan(mut self, tests: Vec<PlannedTest>) -> Self {
+        self.plan = Arc::new(tests);
+        self
+    }
+
+    pub fn record(&self, record: TestRecord) {
+        for listener in self.listeners.iter() {
+            listener.finished(&record);
+        }
+        self.records
+            .lock()
+            .expect("test registry poisoned")
+            .push(record);
+    }
+
+    /// Removes the check records under `path`.
+    fn discard_checks(&self, path: &[String]) {
+        self.records
+            .lock()
+            .expect("test registry poisoned")
+            .retain(|record| record.kind != RecordKind::Check || !record.path.starts_with(path));
+    }
+
+    pub fn records(&self) -> Vec<TestRecord> {
+        self.records.lock().expect("test registry poisoned").clone()
+    }
+
+    /// Every call made to the target, in every scope.
+    pub fn calls(&self) -> Vec<CallRecord> {
+        self.calls.lock().expect("test registry poisoned").clone()
+    }
+
+    pub fn failures(&self) -> Vec<TestRecord> {
+        self.records
+            .lock()
+            .expect("test registry poisoned")
+            .iter()
+            .filter(|record| record.outcome.is_failure())
+            .cloned()
+            .collect()
+    }
+
+    /// Names of the planned tests that have no record, in every scope.
+    pub fn not_run(&self) -> Vec<String> {
+        let roots = self.roots.lock().expect("test registry poisoned").clone();
+        roots
+            .iter()
+            .flat_map(|root| {
+                self.not_run_in(root)
+                    .into_iter()
+                    .map(move |name| format!("{} / {}", root, name))
+            })
+            .collect()
+    }
+
+    fn not_run_in(&self, root: &str) -> Vec<&'static str> {
+        let records = self.records.lock().expect("test registry poisoned");
+        self.plan
+            .iter()
+            .filter(|test| {
+                !records.iter().any(|r| {
+                    r.kind == RecordKind::Test
+                        && r.name == test.name
+                        && r.path.first().map(String::as_str) == Some(root)
+                })
+            })
+            .map(|test| test.name)
+            .collect()
+    }
+
+    pub fn summary(&self) -> Summary {
+        let mut summary = summarize(self.records.lock().expect("test registry poisoned").iter());
+        summary.not_run = self.not_run().len();
+        summary
+    }
+
+    /// Summary of the tests ran in the scope called `root`.
+    pub fn summary_of(&self, root: &str) -> Summary {
+        let mut summary = summarize(
+            self.records
+                .lock()
+                .expect("test registry poisoned")
+                .iter()
+                .filter(|record| record.path.first().map(String::as_str) == Some(root)),
+        );
+        summary.not_run = self.not_run_in(root).len();
+        summary
+    }
+
+    /// Computes the summary of the run and notifies listeners that it is over.
+    pub fn finish(&self) -> Summary {
+        let summary = self.summary();
+        for listener in self.listeners.iter() {
+            listener.completed(&summary);
+        }
+        summary
+    }
+}
+
+fn summarize<'a>(records: impl Iterator<Item = &'a TestRecord>) -> Summary {
+    records.fold(Summary::default(), |mut summary, record| {
+        summary.total += 1;
+        match record.outcome {
+            Outcome::Passed => {
+                summary.passed += 1;
+                summary.total_time += record.duration;
+            }
+            Outcome::Failed => summary.failed += 1,
+            Outcome::Skipped => summary.skipped += 1,
+            Outcome::TimedOut => summary.timed_out += 1,
+        }
+        summary
+    })
+}
+
+/// The runner and span path a test is currently executing in.
+#[derive(Clone)]
+pub struct TestContext {
+    runner: TestRunner,
+    path: Vec<String>,
+}
+
+impl TestContext {
+    pub fn current() -> Self {
+        Self::try_current().expect("tests must be run inside of `TestRunner::scope`")
+    }
+
+    /// The current context, if running inside of `TestRunner::scope`.
+    pub fn try_current() -> Option<Self> {
+        CONTEXT.try_with(Clone::clone).ok()
+    }
+
+    pub fn started(&self, name: &str) {
+        for listener in self.runner.listeners.iter() {
+            listener.started(&self.path, name);
+        }
+    }
+
+    pub fn passed(
+        &self,
+        kind: RecordKind,
+        name: &str,
+        duration: Duration,
+        response: Option<String>,
+    ) {
+        self.record(kind, name, duration, Outcome::Passed, None, response);
+    }
+
+    pub fn failed(&self, kind: RecordKind, name: &str, duration: Duration, error: String) {
+        self.record(kind, name, duration, Outcome::Failed, Some(error), None);
+    }
+
+    pub fn timed_out(&self, name: &str, duration: Duration) {
+        self.record(
+            RecordKind::Test,
+            name,
+            duration,
+            Outcome::TimedOut,
+            Some(format!("timed out after {} secs", duration.as_secs_f64())),
+            None,
+        );
+    }
+
+    pub fn skipped(&self, name: &str, reason: String) {
+        self.record(
+            RecordKind::Test,
+            name,
+            Duration::ZERO,
+            Outcome::Skipped,
+            Some(reason),
+            None,
+        );
+    }
+
+    fn record(
+        &self,
+        kind: RecordKind,
+        name: &str,
+        duration: Duration,
+        outcome: Outcome,
+        error: Option<String>,
+        response: Option<String>,
+    ) {
+        self.runner.record(TestRecord {
+            kind,
+            name: name.to_string(),
+            path: self.path.clone(),
+            duration,
+            outcome,
+            error,
+            response,
+        });
+    }
+
+    /// Records a call the current test made to `endpoint`.
+    pub fn called(&self, endpoint: &'static str, ok: bool) {
+        self.runner
+            .calls
+            .lock()
+            .expect("test registry poisoned")
+            .push(CallRecord {
+                endpoint,
+                path: self.path.clone(),
+                ok,
+            });
+    }
+
+    /// Removes the checks recorded by the test called `name` so far, when
+    /// it's about to be retried.
+    ///
+    /// Listeners were already sent them.
+    pub fn discard_checks(&self, name: &str) {
+        self.runner.discard_checks(&self.child([name]).path);
+    }
+
+    /// The context of tests nested under `path`.
+    pub fn child<'a>(&self, path: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut context = self.clone();
+        context.path.extend(path.into_iter().map(str::to_string));
+        context
+    }
+
+    /// Runs `fut` as a child of the test called `name`.
+    pub async fn nested<Fut: Future>(&self, name: &str, fut: Fut) -> Fut::Output {
+        CONTEXT.scope(self.child([name]), fut).await
+    }
+}
+
+/// Awaits `call` to `endpoint`, or to the endpoint answering with a `T` if
+/// `None`, and records it with its outcome for the coverage report.
+///
+/// Calls made outside of a test, like the fuzzer's login, aren't recorded.
+#[doc(hidden)]
+pub async fn recorded<T, E>(
+    endpoint: Option<&'static str>,
+    call: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    let result = call.await;
+    let endpoint = endpoint.or_else(coverage::endpoint_of::<T>);
+    if let (Some(context), Some(endpoint)) = (TestContext::try_current(), endpoint) {
+        context.called(endpoint, result.is_ok());
+    }
+    result
+}
+
+/// Calls `$request` with `$client`, recording the call and whether it
+/// succeeded for the coverage report:
+///
+/// ```ignore
+/// let response = call!(client, GetGuildListRequest {}).await?;
+/// ```
+#[macro_export]
+macro_rules! call {
+    ($client:expr, $request:expr $(,)?) => {
+        $crate::runner::recorded(None, $client.call($request))
+    };
+}
+
+/// Awaits `$call`, a client method calling the endpoint of `$endpoint`
+/// without going through `call`, recording it like [`call!`].
+#[macro_export]
+macro_rules! called {
+    ($endpoint:ty, $call:expr $(,)?) => {
+        $crate::runner::recorded(
+            Some(<$endpoint as harmony_rust_sdk::api::Endpoint>::ENDPOINT_PATH),
+            $call,
+        )
+    };
+}
@@ -0,0 +1,317 @@
+//! Sessions cached between runs, so clients skip the auth flow while the
+//! target still accepts their last session, and tests of what a target does
+//! with sessions that were revoked.
+
+use std::time::Duration;
+
+use harmony_rust_sdk::{
+    api::{
+        auth::{
+            BeginAuthRequest, CheckLoggedInRequest, NextStepRequest, Session, StreamStepsRequest,
+        },
+        chat::{EventSource, GetGuildListRequest, SendMessageRequest, StreamEventsRequest},
+    },
+    client::{api::chat::message::SendMessage, Client},
+};
+use hyper::header::AUTHORIZATION;
+use tracing::warn;
+
+use crate::{
+    auth::{self, Profile, Transcript},
+    call, called, check, check_err, check_matches, check_some,
+    config::{Config, TestData},
+    expect::Expect,
+    fixtures::{self, ChannelFixture, GuildFixture},
+    plan::Tag,
+    suite::{Suite, TestResult},
+};
+
+/// Name of the test creating the channel the session tests post in.
+pub const CHANNEL: &str = "provision session channel";
+/// How long an event stream of a revoked session may stay open.
+const STREAM_CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Why the tests of revoked sessions are skipped on most targets.
+const REVOKE_UNSUPPORTED: &str = "the target has no revoke_url";
+
+fn key(data: &TestData, email: &str) -> String {
+    format!("{} {}", data.name, email)
+}
+
+/// The session last saved for `email` on `data`'s target.
+pub fn cached(config: &Config, data: &TestData, email: &str) -> Option<Session> {
+    let path = config.session_cache.as_deref()?;
+    session_cache::load(path, &key(data, email))
+        .map_err(|err| warn!("{}", err))
+        .ok()?
+}
+
+/// Saves `session` as the one of `email` on `data`'s target.
+///
+/// The cache is only an optimization, so failing to write it is just logged.
+pub fn save(config: &Config, data: &TestData, email: &str, session: &Session) {
+    let path = match config.session_cache.as_deref() {
+        Some(path) => path,
+        None => return,
+    };
+    if let Err(err) = session_cache::save(path, &key(data, email), session) {
+        warn!("{}", err);
+    }
+}
+
+/// Connects to `data`'s target with `session`, if it still accepts it.
+///
+/// Otherwise the client is left unauthenticated.
+pub async fn connect(
+    data: &TestData,
+    session: Option<Session>,
+) -> TestResult<(Client, Transcript)> {
+    let mut transcript = Transcript::default();
+    if let Some(session) = session {
+        let client = Client::new(data.server.parse()?, Some(session)).await?;
+        match call!(client, CheckLoggedInRequest::new()).await {
+            Ok(_) => {
+                transcript.push("restored cached session".to_string());
+                return Ok((client, transcript));
+            }
+            Err(err) => transcript.push(format!("cached session refused: {}", err)),
+        }
+    }
+    let client = Client::new(data.server.parse()?, None).await?;
+    Ok((client, transcript))
+}
+
+/// Authenticates `client` as `email`, unless it already restored a session,
+/// and caches the new session.
+pub async fn authenticate(
+    config: &Config,
+    data: &TestData,
+    client: &Client,
+    email: &str,
+    username: &str,
+) -> TestResult<Transcript> {
+    if client.auth_status().is_authenticated() {
+        let mut transcript = Transcript::default();
+        transcript.push("using restored session".to_string());
+        return Ok(transcript);
+    }
+    let transcript = auth::authenticate(client, &Profile::account(config, email, username)).await?;
+    if let Some(session) = client.auth_status().session() {
+        save(config, data, email, session);
+    }
+    Ok(transcript)
+}
+
+/// Connects to `data`'s target as `email`, with `session` if it's still
+/// accepted and through the auth flow otherwise.
+pub async fn login(
+    config: &Config,
+    data: &TestData,
+    email: &str,
+    username: &str,
+    session: Option<Session>,
+) -> TestResult<(Client, Transcript)> {
+    let (client, mut transcript) = connect(data, session).await?;
+    let authenticated = authenticate(config, data, &client, email, username).await?;
+    transcript.lines.extend(authenticated.lines);
+    Ok((client, transcript))
+}
+
+/// Adds the tests of restoring sessions.
+pub fn add(suite: &mut Suite, config: &'static Config, data: &'static TestData) {
+    // "client auth" is skipped once a session is cached, so this keeps the
+    // auth flow tested on every run
+    suite
+        .test(
+            "fresh login",
+            &["client connection"],
+            &[Tag::Auth],
+            move |_| async move {
+                let client = Client::new(data.server.parse()?, None).await?;
+                let transcript = auth::authenticate(
+                    &client,
+                    &Profile::account(config, &config.email, "rust_sdk_test"),
+                )
+                .await?;
+                check!(client.auth_status().is_authenticated(), true);
+                call!(client, CheckLoggedInRequest::new()).await?;
+                TestResult::Ok(transcript)
+            },
+        )
+        .covers::<BeginAuthRequest>()
+        .covers::<StreamStepsRequest>()
+        .covers::<NextStepRequest>()
+        .covers::<CheckLoggedInRequest>();
+
+    suite
+        .test(
+            "session restore",
+            &["client auth"],
+            &[Tag::Auth],
+            move |deps| async move {
+                let owner = deps.get::<Client>("client connection");
+                let session = owner.auth_status().session().cloned();
+                check_some!(session).hard()?;
+                let (client, transcript) = connect(data, session).await?;
+                check!(client.auth_status().is_authenticated(), true);
+                check!(
+                    client.auth_status().session().map(|s| s.user_id),
+                    owner.auth_status().session().map(|s| s.user_id)
+                );
+                call!(client, CheckLoggedInRequest::new()).await?;
+                TestResult::Ok(transcript)
+            },
+        )
+        .covers::<CheckLoggedInRequest>();
+
+    suite
+        .test(
+            "invalid session fallback",
+            &["client auth"],
+            &[Tag::Auth],
+            move |deps| async move {
+                let owner = deps.get::<Client>("client connection");
+                let user_id = crate::user_id(&owner)?;
+                let (client, transcript) = login(
+                    config,
+                    data,
+                    &config.email,
+                    "rust_sdk_test",
+                    Some(unknown_session(user_id)),
+                )
+                .await?;
+                check!(
+                    transcript
+                        .lines
+                        .iter()
+                        .any(|line| line.starts_with("cached session refused")),
+                    true
+                );
+                check!(
+                    client.auth_status().session().map(|s| s.user_id),
+                    Some(user_id)
+                );
+                call!(client, CheckLoggedInRequest::new()).await?;
+                TestResult::Ok(transcript)
+            },
+        )
+        .covers::<CheckLoggedInRequest>()
+        .covers::<BeginAuthRequest>()
+        .covers::<NextStepRequest>();
+
+    fixtures::channel(suite, CHANNEL);
+    let no_revoke = data.revoke_url.is_none();
+
+    suite
+        .test(
+            "revoked session calls",
+            &["client auth"],
+            &[Tag::Auth],
+            move |deps| async move {
+                let owner = deps.get::<Client>("client connection");
+                let (client, session) = fresh_login(config, data).await?;
+                call!(client, CheckLoggedInRequest::new()).await?;
+                revoke(data, &session).await?;
+                check_err!(
+                    call!(client, CheckLoggedInRequest::new()).await,
+                    "h.bad-session"
+                );
+                check_err!(call!(client, GetGuildListRequest {}).await, "h.bad-session");
+
+                // the token is refused from any client, not just the one that logged out
+                let reused = Client::new(data.server.parse()?, Some(session)).await?;
+                check_err!(
+                    call!(reused, CheckLoggedInRequest::new()).await,
+                    "h.bad-session"
+                );
+                // the owner's own session is unaffected
+                call!(owner, CheckLoggedInRequest::new()).await
+            },
+        )
+        .skip_if(no_revoke, REVOKE_UNSUPPORTED)
+        .covers::<BeginAuthRequest>()
+        .covers::<StreamStepsRequest>()
+        .covers::<NextStepRequest>()
+        .covers::<CheckLoggedInRequest>()
+        .covers::<GetGuildListRequest>();
+
+    suite
+        .test(
+            "revoked session events",
+            &["client auth", CHANNEL],
+            &[Tag::Auth, Tag::Chat],
+            move |deps| async move {
+                let owner = deps.get::<Client>("client connection");
+                let guild = deps.get::<GuildFixture>(fixtures::GUILD);
+                let channel = deps.get::<ChannelFixture>(CHANNEL);
+                let (client, session) = fresh_login(config, data).await?;
+                let mut events = Expect::new(
+                    called!(
+                        StreamEventsRequest,
+                        client.subscribe_events(vec![EventSource::Guild(guild.guild_id)])
+                    )
+                    .await?,
+                )
+                .timeout(STREAM_CLOSE_TIMEOUT);
+                revoke(data, &session).await?;
+
+                // a message the stream would carry if it was still open
+                call!(
+                    owner,
+                    SendMessage::new(guild.guild_id, channel.channel_id).text("revoked")
+                )
+                .await?;
+                let closed = events.closed().await;
+                check_matches!(closed, Ok(()));
+
+                let reused = Client::new(data.server.parse()?, Some(session)).await?;
+                let subscribed = called!(
+                    StreamEventsRequest,
+                    reused.subscribe_events(vec![EventSource::Guild(guild.guild_id)])
+                )
+                .await;
+                check_matches!(subscribed, Err(_));
+                TestResult::Ok(())
+            },
+        )
+        .skip_if(no_revoke, REVOKE_UNSUPPORTED)
+        .covers::<StreamEventsRequest>()
+        .covers::<SendMessageRequest>();
+}
+
+/// Logs in as the tester with a client of its own, whatever is cached.
+async fn fresh_login(config: &Config, data: &TestData) -> TestResult<(Client, Session)> {
+    let client = Client::new(data.server.parse()?, None).await?;
+    auth::authenticate(
+        &client,
+        &Profile::account(config, &config.email, "rust_sdk_test"),
+    )
+    .await?;
+    let session = client
+        .auth_status()
+        .session()
+        .cloned()
+        .ok_or("no session after logging in")?;
+    Ok((client, session))
+}
+
+/// Ends `session` through the target's revocation url.
+async fn revoke(data: &TestData, session: &Session) -> TestResult<()> {
+    let url = data.revoke_url.as_deref().ok_or(REVOKE_UNSUPPORTED)?;
+    let request = hyper::Request::post(url)
+        .header(AUTHORIZATION, session.session_token.as_str())
+        .body(hyper::Body::empty())?;
+    let response = hyper::Client::new().request(request).await?;
+    if !response.status().is_success() {
+        return Err(format!("revoking the session failed with {}", response.status()).into());
+    }
+    Ok(())
+}
+
+/// A session of `user_id` the target never gave out.
+fn unknown_session(user_id: u64) -> Session {
+    Session {
+        user_id,
+        session_token: crate::random_string(),
+        ..Default::default()
+    }
+}
@@ -0,0 +1,442 @@
+use std::{
+    any::{type_name, Any},
+    collections::HashMap,
+    error::Error,
+    fmt::Debug,
+    future::Future,
+    io,
+    pin::Pin,
+    rc::Rc,
+    time::Duration,
+};
+
+use harmony_rust_sdk::api::{
+    exports::hrpc::exports::futures_util::{stream::FuturesUnordered, StreamExt},
+    Endpoint,
+};
+use tokio::time::Instant;
+use tokio_tungstenite::tungstenite::Error as WsError;
+use tracing::{error, info, info_span, warn, Instrument};
+
+use crate::{
+    plan::{self, PlannedTest, Tag},
+    runner::{RecordKind, TestContext},
+};
+
+/// Result of test bodies that can fail in more than one way.
+pub type TestResult<T> = Result<T, Box<dyn Error>>;
+
+type Output = Rc<dyn Any>;
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T>>>;
+type Body = Box<dyn Fn(Deps) -> BoxFuture<TestResult<(Output, String)>>>;
+
+/// How long tests may take, and how often they're retried after transient errors.
+#[derive(Debug, Clone, Copy)]
+pub struct Policy {
+    pub timeout: Duration,
+    pub retries: u32,
+    /// Delay before the first retry, doubled after every retry.
+    pub backoff: Duration,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+            retries: 0,
+            backoff: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Outputs of the tests a test depends on, directly or not.
+#[derive(Clone)]
+pub struct Deps {
+    test: &'static str,
+    outputs: HashMap<&'static str, Output>,
+}
+
+impl Deps {
+    fn of(
+        plan: &[PlannedTest],
+        outputs: &HashMap<&'static str, Output>,
+        test: &'static str,
+    ) -> Self {
+        Self {
+            test,
+            outputs: plan::dependencies(plan, test)
+                .into_iter()
+                .filter_map(|dep| Some((dep, Rc::clone(outputs.get(dep)?))))
+                .collect(),
+        }
+    }
+
+    /// Returns the output of the test called `name`.
+    ///
+    /// Panics if this test doesn't depend on it, or if the output isn't a `T`.
+    pub fn get<T: Any>(&self, name: &str) -> Rc<T> {
+        self.outputs
+            .get(name)
+            .unwrap_or_else(|| panic!("{} does not depend on {}", self.test, name))
+            .clone()
+            .downcast()
+            .unwrap_or_else(|_| panic!("output of {} is not a {}", name, type_name::<T>()))
+    }
+}
+
+struct Node {
+    plan: PlannedTest,
+    body: Body,
+    timeout: Option<Duration>,
+    retries: Option<u32>,
+    /// Why the test can't run against the target, if it can't.
+    skip: Option<&'static str>,
+}
+
+/// Tests and the tests they depend on.
+///
+/// A test starts once every test it depends on passed, so independent tests
+/// run concurrently. Tests depending on a failed or skipped test are skipped.
+/// Teardowns run one by one after every test finished.
+#[derive(Default)]
+pub struct Suite {
+    nodes: Vec<Node>,
+    policy: Policy,
+    deadline: Option<Instant>,
+}
+
+impl Suite {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a test, whose output is made available to the tests depending on it.
+    pub fn test<F, Fut, Out, Err>(
+        &mut self,
+        name: &'static str,
+        deps: &[&'static str],
+        tags: &'static [Tag],
+        body: F,
+    ) -> &mut Self
+    where
+        F: Fn(Deps) -> Fut + 'static,
+        Fut: Future<Output = Result<Out, Err>> + 'static,
+        Out: Debug + 'static,
+        Err: Into<Box<dyn Error>>,
+    {
+        self.add(name, deps, tags, false, body)
+    }
+
+    /// Adds a test cleaning up after the tests depending on `deps`, which runs
+    /// after every other test whether they passed or not.
+    pub fn teardown<F, Fut, Out, Err>(
+        &mut self,
+        name: &'static str,
+        deps: &[&'static str],
+        tags: &'static [Tag],
+        body: F,
+    ) -> &mut Self
+    where
+        F: Fn(Deps) -> Fut + 'static,
+        Fut: Future<Output = Result<Out, Err>> + 'static,
+        Out: Debug + 'static,
+        Err: Into<Box<dyn Error>>,
+    {
+        self.add(name, deps, tags, true, body)
+    }
+
+    fn add<F, Fut, Out, Err>(
+        &mut self,
+        name: &'static str,
+        deps: &[&'static str],
+        tags: &'static [Tag],
+        teardown: bool,
+        body: F,
+    ) -> &mut Self
+    where
+        F: Fn(Deps) -> Fut + 'static,
+        Fut: Future<Output = Result<Out, Err>> + 'static,
+        Out: Debug + 'static,
+        Err: Into<Box<dyn Error>>,
+    {
+        let body: Body = Box::new(move |deps: Deps| -> BoxFuture<_> {
+            let fut = body(deps);
+            Box::pin(async move {
+                match fut.await {
+                    Ok(out) => {
+                        let response = format!("{:?}", out);
+                        Ok((Rc::new(out) as Output, response))
+                    }
+                    Err(err) => Err(err.into()),
+                }
+            })
+        });
+        self.nodes.push(Node {
+            plan: PlannedTest {
+                name,
+                deps: deps.to_vec(),
+                tags,
+                teardown,
+                endpoints: Vec::new(),
+            },
+            body,
+            timeout: None,
+            retries: None,
+            skip: None,
+        });
+        self
+    }
+
+    /// Sets the policy of tests that don't override it.
+    pub fn policy(&mut self, policy: Policy) -> &mut Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Stops running tests at `deadline`, only running teardowns after it.
+    pub fn deadline(&mut self, deadline: Instant) -> &mut Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Overrides the timeout of the last added test.
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        if let Some(node) = self.nodes.last_mut() {
+            node.timeout = Some(timeout);
+        }
+        self
+    }
+
+    /// Overrides how often the last added test is retried after transient errors.
+    pub fn retries(&mut self, retries: u32) -> &mut Self {
+        if let Some(node) = self.nodes.last_mut() {
+            node.retries = Some(retries);
+        }
+        self
+    }
+
+    /// Skips the last added test with `reason` when `skip` holds, for tests of
+    /// what only some targets can do.
+    pub fn skip_if(&mut self, skip: bool, reason: &'static str) -> &mut Self {
+        if let Some(node) = self.nodes.last_mut() {
+            node.skip = skip.then_some(reason);
+        }
+        self
+    }
+
+    /// Marks the last added test as calling the endpoint of `E`, for the
+    /// coverage report.
+    pub fn covers<E: Endpoint>(&mut self) -> &mut Self {
+        if let Some(node) = self.nodes.last_mut() {
+            node.plan.endpoints.push(E::ENDPOINT_PATH);
+        }
+        self
+    }
+
+    pub fn plan(&self) -> Vec<PlannedTest> {
+        self.nodes.iter().map(|node| node.plan.clone()).collect()
+    }
+
+    /// Drops every test that isn't in `names`.
+    pub fn retain(&mut self, names: &[&str]) {
+        self.nodes.retain(|node| names.contains(&node.plan.name));
+    }
+
+    /// Runs every test, nesting their records under the current test context.
+    ///
+    /// Returns whether the deadline cut the run short. Tests still running
+    /// then are dropped without a record, but teardowns still run.
+    pub async fn run(self) -> bool {
+        let context = TestContext::current();
+        let planned = self.plan();
+        let policy = self.policy;
+        let deadline = self.deadline;
+        let mut timed_out = false;
+        let (teardowns, mut pending): (Vec<_>, Vec<_>) =
+            self.nodes.into_iter().partition(|node| node.plan.teardown);
+        let mut outputs = HashMap::new();
+        let mut blocked = HashMap::new();
+        let mut running = FuturesUnordered::new();
+
+        loop {
+            let mut changed = true;
+            while changed {
+                changed = false;
+                let mut i = 0;
+                while i < pending.len() {
+                    let node = &pending[i];
+                    let blocker = node.plan.deps.iter().find_map(|dep| {
+                        let failed: &bool = blocked.get(dep)?;
+                        Some((*dep, if *failed { "failed" } else { "was skipped" }))
+                    });
+                    if let Some((dep, why)) = blocker {
+                        let node = pending.remove(i);
+                        let reason = format!("depends on {}, which {}", dep, why);
+                        info!("Skipping {}: {}", node.plan.name, reason);
+                        context
+                            .child(path(&planned, node.plan.name))
+                            .skipped(node.plan.name, reason);
+                        blocked.insert(node.plan.name, false);
+                        changed = true;
+                    } else if node.plan.deps.iter().all(|dep| outputs.contains_key(dep)) {
+                        let node = pending.remove(i);
+                        let parent = context.child(path(&planned, node.plan.name));
+                        if let Some(reason) = node.skip {
+                            info!("Skipping {}: {}", node.plan.name, reason);
+                            parent.skipped(node.plan.name, reason.to_string());
+                            blocked.insert(node.plan.name, false);
+                        } else {
+                            let deps = Deps::of(&planned, &outputs, node.plan.name);
+                            running.push(run_node(parent, node, deps, policy));
+                        }
+                        changed = true;
+                    } else {
+                        i += 1;
+                    }
+                }
+            }
+
+            let next = match deadline {
+                Some(deadline) => match tokio::time::timeout_at(deadline, running.next()).await {
+                    Ok(next) => next,
+                    Err(_) => {
+                        timed_out = true;
+                        break;
+                    }
+                },
+                None => running.next().await,
+            };
+            match next {
+                Some((name, Some(output))) => {
+                    outputs.insert(name, output);
+                }
+                Some((name, None)) => {
+                    blocked.insert(name, true);
+                }
+                None => break,
+            }
+        }
+
+        drop(running);
+
+        for node in pending {
+            let reason = if timed_out {
+                "the run timed out".to_string()
+            } else {
+                format!(
+                    "depends on tests that aren't in the suite: {:?}",
+                    node.plan.deps
+                )
+            };
+            context
+                .child(path(&planned, node.plan.name))
+                .skipped(node.plan.name, reason);
+        }
+
+        for node in teardowns {
+            let parent = context.child(path(&planned, node.plan.name));
+            if let Some(dep) = node
+                .plan
+                .deps
+                .iter()
+                .find(|dep| !outputs.contains_key(*dep))
+            {
+                let reason = format!("depends on {}, which did not pass", dep);
+                info!("Skipping {}: {}", node.plan.name, reason);
+                parent.skipped(node.plan.name, reason);
+                continue;
+            }
+            let deps = Deps::of(&planned, &outputs, node.plan.name);
+            run_node(parent, node, deps, policy).await;
+        }
+        timed_out
+    }
+}
+
+/// Names of the tests `name` is nested under, outermost first.
+fn path<'a>(plan: &'a [PlannedTest], name: &str) -> impl Iterator<Item = &'a str> {
+    let mut parents = plan::parents(plan, name)
+        .map(|t| t.name)
+        .collect::<Vec<_>>();
+    parents.reverse();
+    parents.into_iter()
+}
+
+async fn run_node(
+    parent: TestContext,
+    node: Node,
+    deps: Deps,
+    policy: Policy,
+) -> (&'static str, Option<Output>) {
+    let name = node.plan.name;
+    let timeout = node.timeout.unwrap_or(policy.timeout);
+    let retries = node.retries.unwrap_or(policy.retries);
+    async move {
+        info!("Testing {}...", name);
+        parent.started(name);
+        let mut attempt = 0;
+        loop {
+            let ins = Instant::now();
+            let result =
+                tokio::time::timeout(timeout, parent.nested(name, (node.body)(deps.clone()))).await;
+            let time_passed = ins.elapsed();
+            match result {
+                Ok(Ok((output, response))) => {
+                    info!("successful in {} ns", time_passed.as_nanos());
+                    info!("response: {}", response);
+                    parent.passed(RecordKind::Test, name, time_passed, Some(response));
+                    return (name, Some(output));
+                }
+                Ok(Err(err)) if attempt < retries && is_transient(err.as_ref()) => {
+                    // only the checks of the attempt that counts are kept
+                    parent.discard_checks(name);
+                    let delay = policy.backoff * 2u32.saturating_pow(attempt);
+                    warn!(
+                        "transient error, retrying in {} ms: {}",
+                        delay.as_millis(),
+                        err
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Ok(Err(err)) => {
+                    error!("error occured: {}", err);
+                    parent.failed(RecordKind::Test, name, time_passed, err.to_string());
+                    return (name, None);
+                }
+                Err(_) => {
+                    error!("timed out after {} secs", timeout.as_secs_f64());
+                    parent.timed_out(name, time_passed);
+                    return (name, None);
+                }
+            }
+        }
+    }
+    .instrument(info_span!("test", name = %name))
+    .await
+}
+
+/// Whether `err` is a connection problem rather than an error returned by
+/// the server, going by the transport errors among its sources.
+fn is_transient(err: &(dyn Error + 'static)) -> bool {
+    std::iter::successors(Some(err), |err| err.source()).any(|err| {
+        if let Some(err) = err.downcast_ref::<hyper::Error>() {
+            err.is_connect() || err.is_closed() || err.is_incomplete_message() || err.is_timeout()
+        } else if let Some(err) = err.downcast_ref::<io::Error>() {
+            matches!(
+                err.kind(),
+                io::ErrorKind::ConnectionRefused
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::NotConnected
+                    | io::ErrorKind::BrokenPipe
+                    | io::ErrorKind::TimedOut
+            )
+        } else {
+            matches!(
+                err.downcast_ref::<WsError>(),
+                Some(WsError::ConnectionClosed | WsError::AlreadyClosed)
+            )
+        }
+    })
+}
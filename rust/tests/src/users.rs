@@ -0,0 +1,164 @@
+//! Accounts other than the tester's, to test how users of a guild interact.
+//!
+//! They share the tester's password, and are registered on the first run.
+//! Their sessions are cached like the tester's.
+
+use harmony_rust_sdk::{
+    api::chat::*,
+    client::{
+        api::chat::message::{GetChannelMessages, SendMessage},
+        Client,
+    },
+};
+
+use crate::{
+    call, check, check_contains, check_err, check_matches, check_some,
+    config::{Config, TestData},
+    fixtures::{self, ChannelFixture, GuildFixture},
+    plan::Tag,
+    session,
+    suite::{Suite, TestResult},
+};
+
+/// Name of the test authenticating a member of the provisioned guild.
+pub const MEMBER: &str = "member auth";
+/// Name of the test authenticating a user outside of the provisioned guild.
+pub const OUTSIDER: &str = "outsider auth";
+/// Name of the test creating the channel the scenarios post in.
+pub const CHANNEL: &str = "provision users channel";
+
+/// Adds the tests authenticating the other accounts, and the scenarios where
+/// they interact with the tester in the provisioned guild.
+pub fn add(suite: &mut Suite, config: &'static Config, data: &'static TestData) {
+    for (name, role) in [(MEMBER, "member"), (OUTSIDER, "outsider")] {
+        suite.test(
+            name,
+            &["client connection"],
+            &[Tag::Auth],
+            move |_| async move {
+                let email = config.email_for(role);
+                let cached = session::cached(config, data, &email);
+                let (client, _) = session::login(
+                    config,
+                    data,
+                    &email,
+                    &format!("rust_sdk_test_{}", role),
+                    cached,
+                )
+                .await?;
+                check!(client.auth_status().is_authenticated(), true);
+                TestResult::Ok(client)
+            },
+        );
+    }
+
+    fixtures::channel(suite, CHANNEL);
+
+    suite
+        .test(
+            "member joins guild",
+            &[MEMBER, "client auth", fixtures::GUILD],
+            &[Tag::Chat],
+            |deps| async move {
+                let owner = deps.get::<Client>("client connection");
+                let member = deps.get::<Client>(MEMBER);
+                let guild = deps.get::<GuildFixture>(fixtures::GUILD);
+                let response = call!(
+                    member,
+                    JoinGuildRequest {
+                        invite_id: guild.invite.clone(),
+                    }
+                )
+                .await?;
+                check!(response.guild_id, guild.guild_id);
+
+                let member_id = crate::user_id(&member)?;
+                let members = call!(owner, GetGuildMembersRequest::new(guild.guild_id)).await?;
+                check_contains!(members.members, &member_id);
+                TestResult::Ok(members)
+            },
+        )
+        .covers::<JoinGuildRequest>()
+        .covers::<GetGuildMembersRequest>();
+
+    suite
+        .test(
+            "member reads messages",
+            &["member joins guild", CHANNEL, fixtures::GUILD],
+            &[Tag::Chat],
+            |deps| async move {
+                let owner = deps.get::<Client>("client connection");
+                let member = deps.get::<Client>(MEMBER);
+                let guild = deps.get::<GuildFixture>(fixtures::GUILD);
+                let channel = deps.get::<ChannelFixture>(CHANNEL);
+                let text = crate::random_string();
+                let message_id = call!(
+                    owner,
+                    SendMessage::new(guild.guild_id, channel.channel_id).text(&text)
+                )
+                .await?
+                .message_id;
+
+                let response = call!(
+                    member,
+                    GetChannelMessages::new(guild.guild_id, channel.channel_id)
+                )
+                .await?;
+                let message = response
+                    .messages
+                    .iter()
+                    .find(|m| m.message_id == message_id)
+                    .and_then(|m| m.message.as_ref());
+                check_some!(message);
+                if let Some(message) = message {
+                    check!(message.text(), Some(text.as_str()));
+                    check!(message.author_id, crate::user_id(&owner)?);
+                }
+                TestResult::Ok(response)
+            },
+        )
+        .covers::<SendMessageRequest>()
+        .covers::<GetChannelMessagesRequest>();
+
+    suite
+        .test(
+            "member can't delete guild",
+            &["member joins guild", fixtures::GUILD],
+            &[Tag::Chat],
+            move |deps| async move {
+                let owner = deps.get::<Client>("client connection");
+                let member = deps.get::<Client>(MEMBER);
+                let guild = deps.get::<GuildFixture>(fixtures::GUILD);
+                let deleted = call!(member, DeleteGuildRequest::new(guild.guild_id)).await;
+                check_err!(deleted, in data.refusal("not-enough-permissions"));
+                call!(owner, GetGuildRequest::new(guild.guild_id)).await
+            },
+        )
+        .covers::<DeleteGuildRequest>()
+        .covers::<GetGuildRequest>();
+
+    suite
+        .test(
+            "outsider can't read guild",
+            &[OUTSIDER, fixtures::GUILD],
+            &[Tag::Chat],
+            move |deps| async move {
+                let outsider = deps.get::<Client>(OUTSIDER);
+                let guild = deps.get::<GuildFixture>(fixtures::GUILD);
+                let preview = call!(outsider, PreviewGuildRequest::new(guild.invite.clone())).await;
+                check_matches!(preview, Ok(_));
+                let info = call!(outsider, GetGuildRequest::new(guild.guild_id)).await;
+                check_err!(info, in data.refusal("not-joined"));
+                let messages = call!(
+                    outsider,
+                    GetChannelMessages::new(guild.guild_id, guild.channel_id)
+                )
+                .await;
+                check_err!(messages, in data.refusal("not-joined"));
+                TestResult::Ok(())
+            },
+        )
+        .covers::<PreviewGuildRequest>()
+        .covers::<GetGuildRequest>()
+        .covers::<GetChannelMessagesRequest>();
+}